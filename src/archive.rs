@@ -0,0 +1,235 @@
+//
+// archive.rs
+// BigDiff-rs
+//
+// Opens zip/jar and tar/tar.gz archives as temporary directory trees so they can be compared like regular folders.
+//
+// Thales Matheus Mendonça Santos - November 2025
+//
+// Archive support: detection by name and safe extraction into private temporary directories.
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read};
+use std::path::{Component, Path, PathBuf};
+
+use flate2::read::GzDecoder;
+use tempfile::TempDir;
+
+use crate::error::{Error, Result};
+
+/// Marker appended to an archive's output folder (`lib/app.jar!/...`).
+pub const ARCHIVE_MARKER: &str = "!";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveKind {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+const ZIP_EXTS: &[&str] = &[
+    ".zip", ".jar", ".war", ".ear", ".aar", ".apk", ".whl", ".nupkg", ".vsix",
+];
+
+/// Caps on what one archive may expand to, so a zip bomb or a self-nesting archive cannot fill
+/// the disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArchiveLimits {
+    /// Total bytes written for all members of one archive.
+    pub max_bytes: u64,
+    /// Members (files, folders, links) read from one archive.
+    pub max_entries: u64,
+    /// Archive levels `--recurse-archives` expands below the compared trees.
+    pub max_depth: usize,
+}
+
+impl Default for ArchiveLimits {
+    fn default() -> Self {
+        ArchiveLimits {
+            max_bytes: 4 * 1024 * 1024 * 1024,
+            max_entries: 100_000,
+            max_depth: 8,
+        }
+    }
+}
+
+impl ArchiveKind {
+    /// Detects the archive type from the file name.
+    pub fn for_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?.to_lowercase();
+        if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ArchiveKind::TarGz)
+        } else if name.ends_with(".tar") {
+            Some(ArchiveKind::Tar)
+        } else if ZIP_EXTS.iter().any(|ext| name.ends_with(ext)) {
+            Some(ArchiveKind::Zip)
+        } else {
+            None
+        }
+    }
+}
+
+/// A directory tree to compare: either a real folder or an archive expanded into a temp dir.
+///
+/// The temporary directory is removed when the tree is dropped.
+#[derive(Debug)]
+pub struct InputTree {
+    pub root: PathBuf,
+    _temp: Option<TempDir>,
+}
+
+impl InputTree {
+    /// Opens `path` as a directory, or extracts it when it is a supported archive.
+    pub fn open(path: &Path, limits: &ArchiveLimits) -> Result<Self> {
+        if path.is_file() {
+            if let Some(kind) = ArchiveKind::for_path(path) {
+                return Self::extract(path, kind, limits);
+            }
+        }
+        Ok(InputTree {
+            root: path.canonicalize().map_err(Error::io(path))?,
+            _temp: None,
+        })
+    }
+
+    /// Extracts an archive into a fresh temporary directory.
+    ///
+    /// Fails, removing what was written, once the archive exceeds `limits`.
+    pub fn extract(path: &Path, kind: ArchiveKind, limits: &ArchiveLimits) -> Result<Self> {
+        let temp = tempfile::Builder::new()
+            .prefix("bigdiff-archive-")
+            .tempdir()
+            .map_err(Error::io(std::env::temp_dir()))?;
+        extract_archive(path, kind, temp.path(), limits)?;
+        Ok(InputTree {
+            root: temp.path().canonicalize().map_err(Error::io(temp.path()))?,
+            _temp: Some(temp),
+        })
+    }
+
+    /// Whether this tree was expanded from an archive.
+    pub fn is_archive(&self) -> bool {
+        self._temp.is_some()
+    }
+}
+
+/// Unpacks regular files and directories; links, devices and entries escaping `dest` are skipped.
+///
+/// Stops with an error as soon as the archive goes over the byte or entry budget in `limits`.
+pub fn extract_archive(
+    path: &Path,
+    kind: ArchiveKind,
+    dest: &Path,
+    limits: &ArchiveLimits,
+) -> Result<()> {
+    let extract = || -> io::Result<()> {
+        let file = BufReader::new(File::open(path)?);
+        let mut budget = Budget {
+            limits,
+            bytes: 0,
+            entries: 0,
+        };
+        match kind {
+            ArchiveKind::Zip => extract_zip(file, dest, &mut budget),
+            ArchiveKind::Tar => extract_tar(file, dest, &mut budget),
+            ArchiveKind::TarGz => extract_tar(GzDecoder::new(file), dest, &mut budget),
+        }
+    };
+    extract().map_err(|source| Error::Archive {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// What an extraction has used so far of its [`ArchiveLimits`].
+struct Budget<'a> {
+    limits: &'a ArchiveLimits,
+    bytes: u64,
+    entries: u64,
+}
+
+impl Budget<'_> {
+    fn entry(&mut self) -> io::Result<()> {
+        self.entries += 1;
+        if self.entries > self.limits.max_entries {
+            return Err(io::Error::other(format!(
+                "archive has more than {} entries",
+                self.limits.max_entries
+            )));
+        }
+        Ok(())
+    }
+
+    fn copy(&mut self, entry: &mut impl Read, out: &mut File) -> io::Result<()> {
+        // One byte past the budget is enough to tell that the archive is over it.
+        let left = self.limits.max_bytes - self.bytes;
+        self.bytes += io::copy(&mut entry.take(left.saturating_add(1)), out)?;
+        if self.bytes > self.limits.max_bytes {
+            return Err(io::Error::other(format!(
+                "archive expands to more than {} bytes",
+                self.limits.max_bytes
+            )));
+        }
+        Ok(())
+    }
+}
+
+fn extract_zip(reader: impl Read + io::Seek, dest: &Path, budget: &mut Budget) -> io::Result<()> {
+    let mut archive = zip::ZipArchive::new(reader)?;
+    for i in 0..archive.len() {
+        budget.entry()?;
+        let mut entry = archive.by_index(i)?;
+        if entry.is_symlink() {
+            continue;
+        }
+        let Some(rel) = entry.enclosed_name().and_then(|p| safe_relative(&p)) else {
+            continue;
+        };
+        let out = dest.join(rel);
+        if entry.is_dir() {
+            fs::create_dir_all(&out)?;
+        } else {
+            write_entry(&mut entry, &out, budget)?;
+        }
+    }
+    Ok(())
+}
+
+fn extract_tar(reader: impl Read, dest: &Path, budget: &mut Budget) -> io::Result<()> {
+    let mut archive = tar::Archive::new(reader);
+    for entry in archive.entries()? {
+        budget.entry()?;
+        let mut entry = entry?;
+        let kind = entry.header().entry_type();
+        let Some(rel) = safe_relative(&entry.path()?) else {
+            continue;
+        };
+        let out = dest.join(rel);
+        if kind.is_dir() {
+            fs::create_dir_all(&out)?;
+        } else if kind.is_file() {
+            write_entry(&mut entry, &out, budget)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_entry(entry: &mut impl Read, out: &Path, budget: &mut Budget) -> io::Result<()> {
+    if let Some(parent) = out.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // Later duplicates win, as with `tar -x`; nothing here creates links, so truncating is safe.
+    budget.copy(entry, &mut File::create(out)?)
+}
+
+// Keeps only plain components so entries cannot land outside the extraction root.
+fn safe_relative(path: &Path) -> Option<PathBuf> {
+    let mut rel = PathBuf::new();
+    for comp in path.components() {
+        match comp {
+            Component::Normal(part) => rel.push(part),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    (!rel.as_os_str().is_empty()).then_some(rel)
+}
@@ -0,0 +1,277 @@
+//
+// bindiff.rs
+// BigDiff-rs
+//
+// Byte-level comparison of modified binary files: sizes, SHA-256 hashes, the coalesced ranges that differ and hex excerpts around them, rendered within a size budget.
+//
+// Thales Matheus Mendonça Santos - November 2025
+//
+// Binary analysis behind `--binary-report`.
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::path::Path;
+
+use sha2::{Digest, Sha256};
+
+use crate::error::{Error, Result};
+
+/// Differences closer than this many equal bytes are reported as one range.
+pub const COALESCE_GAP: u64 = 8;
+
+// Ranges kept in memory; further ones are only counted.
+const MAX_RANGES: usize = 10_000;
+// Equal bytes shown before and after each range.
+const CONTEXT: u64 = 16;
+// Bytes per hex dump row.
+const ROW: u64 = 16;
+// Rows shown per range before the excerpt is cut.
+const MAX_EXCERPT_ROWS: u64 = 16;
+const CHUNK: usize = 64 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Result of [`analyze`].
+pub struct BinaryAnalysis {
+    pub base_size: u64,
+    pub target_size: u64,
+    pub base_sha256: String,
+    pub target_sha256: String,
+    /// Differing byte ranges, in order. Bytes past the end of the shorter file differ.
+    pub ranges: Vec<Range<u64>>,
+    /// Ranges found after the first 10 000, counted but not kept.
+    pub omitted_ranges: u64,
+    /// Bytes inside all ranges, coalesced gaps included.
+    pub differing_bytes: u64,
+}
+
+/// Streams both files once, hashing them and collecting the ranges where they differ.
+pub fn analyze(base: &Path, target: &Path) -> Result<BinaryAnalysis> {
+    let open = |path: &Path| {
+        File::open(path)
+            .map(BufReader::new)
+            .map_err(Error::io(path))
+    };
+    let (mut a, mut b) = (open(base)?, open(target)?);
+    let (mut hash_a, mut hash_b) = (Sha256::new(), Sha256::new());
+    let (mut buf_a, mut buf_b) = (vec![0u8; CHUNK], vec![0u8; CHUNK]);
+    let mut ranges = Ranges::default();
+    let (mut size_a, mut size_b) = (0u64, 0u64);
+
+    loop {
+        let n_a = fill(&mut a, &mut buf_a).map_err(Error::io(base))?;
+        let n_b = fill(&mut b, &mut buf_b).map_err(Error::io(target))?;
+        if n_a == 0 && n_b == 0 {
+            break;
+        }
+        hash_a.update(&buf_a[..n_a]);
+        hash_b.update(&buf_b[..n_b]);
+
+        let offset = size_a.max(size_b);
+        let common = n_a.min(n_b);
+        let mut i = 0;
+        while i < common {
+            if buf_a[i] == buf_b[i] {
+                i += 1;
+                continue;
+            }
+            let start = i;
+            while i < common && buf_a[i] != buf_b[i] {
+                i += 1;
+            }
+            ranges.push(offset + start as u64, offset + i as u64);
+        }
+        // Only the longer file has bytes left from here on.
+        if n_a != n_b {
+            ranges.push(offset + common as u64, offset + n_a.max(n_b) as u64);
+        }
+        size_a += n_a as u64;
+        size_b += n_b as u64;
+    }
+
+    Ok(BinaryAnalysis {
+        base_size: size_a,
+        target_size: size_b,
+        base_sha256: hex::encode(hash_a.finalize()),
+        target_sha256: hex::encode(hash_b.finalize()),
+        ranges: ranges.kept,
+        omitted_ranges: ranges.omitted,
+        differing_bytes: ranges.bytes,
+    })
+}
+
+impl BinaryAnalysis {
+    /// Renders the report: sizes and hashes, then each range with a hex excerpt of both
+    /// versions. Ranges stop being added once the text would exceed `budget` bytes.
+    pub fn render(&self, base: &Path, target: &Path, budget: u64) -> Result<String> {
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "Base (A): {} bytes, sha256 {}",
+            self.base_size, self.base_sha256
+        );
+        let _ = writeln!(
+            out,
+            "Target (B): {} bytes, sha256 {}",
+            self.target_size, self.target_sha256
+        );
+        let total = self.ranges.len() as u64 + self.omitted_ranges;
+        let _ = writeln!(
+            out,
+            "Differing bytes: {} in {} range(s)",
+            self.differing_bytes, total
+        );
+
+        let (mut a, mut b) = (Source::open(base)?, Source::open(target)?);
+        let end = self.base_size.max(self.target_size);
+        for (shown, range) in self.ranges.iter().enumerate() {
+            let mut block = format!(
+                "\n@@ 0x{:08x}..0x{:08x} ({} bytes) @@\n",
+                range.start,
+                range.end,
+                range.end - range.start
+            );
+            excerpt(&mut block, &mut a, &mut b, range, end)?;
+            if out.len() as u64 + block.len() as u64 > budget {
+                let _ = writeln!(
+                    out,
+                    "\n... {} more range(s) not shown: report budget of {budget} bytes reached",
+                    total - shown as u64
+                );
+                return Ok(out);
+            }
+            out += &block;
+        }
+        if self.omitted_ranges > 0 {
+            let _ = writeln!(
+                out,
+                "\n... {} more range(s) not listed",
+                self.omitted_ranges
+            );
+        }
+        Ok(out)
+    }
+}
+
+#[derive(Default)]
+struct Ranges {
+    kept: Vec<Range<u64>>,
+    omitted: u64,
+    bytes: u64,
+    // End of the last range, kept or not, so coalescing continues past the limit.
+    last_end: Option<u64>,
+}
+
+impl Ranges {
+    fn push(&mut self, start: u64, end: u64) {
+        match self.last_end {
+            Some(last) if start <= last + COALESCE_GAP => {
+                self.bytes += end - last;
+                if self.omitted == 0 {
+                    if let Some(range) = self.kept.last_mut() {
+                        range.end = end;
+                    }
+                }
+            }
+            _ => {
+                self.bytes += end - start;
+                if self.kept.len() < MAX_RANGES {
+                    self.kept.push(start..end);
+                } else {
+                    self.omitted += 1;
+                }
+            }
+        }
+        self.last_end = Some(end);
+    }
+}
+
+// Reads until `buf` is full or the reader is exhausted.
+fn fill(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut n = 0;
+    while n < buf.len() {
+        match reader.read(&mut buf[n..])? {
+            0 => break,
+            read => n += read,
+        }
+    }
+    Ok(n)
+}
+
+// Hex rows around `range`: equal rows once, differing rows as `-` (base) and `+` (target).
+fn excerpt(
+    out: &mut String,
+    a: &mut Source,
+    b: &mut Source,
+    range: &Range<u64>,
+    end: u64,
+) -> Result<()> {
+    let first = range.start.saturating_sub(CONTEXT) / ROW * ROW;
+    let last = (range.end + CONTEXT).min(end);
+    let rows = (last - first).div_ceil(ROW);
+    for row in 0..rows.min(MAX_EXCERPT_ROWS) {
+        let offset = first + row * ROW;
+        let (bytes_a, bytes_b) = (a.row(offset)?, b.row(offset)?);
+        if bytes_a == bytes_b {
+            hex_row(out, ' ', offset, &bytes_a);
+            continue;
+        }
+        if !bytes_a.is_empty() {
+            hex_row(out, '-', offset, &bytes_a);
+        }
+        if !bytes_b.is_empty() {
+            hex_row(out, '+', offset, &bytes_b);
+        }
+    }
+    if rows > MAX_EXCERPT_ROWS {
+        let _ = writeln!(out, "  ... {} more row(s)", rows - MAX_EXCERPT_ROWS);
+    }
+    Ok(())
+}
+
+struct Source<'a> {
+    file: File,
+    path: &'a Path,
+}
+
+impl<'a> Source<'a> {
+    fn open(path: &'a Path) -> Result<Self> {
+        let file = File::open(path).map_err(Error::io(path))?;
+        Ok(Source { file, path })
+    }
+
+    // Up to one row of bytes at `offset`; shorter (or empty) past the end of the file.
+    fn row(&mut self, offset: u64) -> Result<Vec<u8>> {
+        let mut row = vec![0u8; ROW as usize];
+        let n = self
+            .file
+            .seek(SeekFrom::Start(offset))
+            .and_then(|_| fill(&mut self.file, &mut row))
+            .map_err(Error::io(self.path))?;
+        row.truncate(n);
+        Ok(row)
+    }
+}
+
+fn hex_row(out: &mut String, tag: char, offset: u64, bytes: &[u8]) {
+    let _ = write!(out, "{tag} {offset:08x} ");
+    for i in 0..ROW as usize {
+        match bytes.get(i) {
+            Some(byte) => {
+                let _ = write!(out, " {byte:02x}");
+            }
+            None => out.push_str("   "),
+        }
+    }
+    let ascii: String = bytes
+        .iter()
+        .map(|b| {
+            if b.is_ascii_graphic() || *b == b' ' {
+                *b as char
+            } else {
+                '.'
+            }
+        })
+        .collect();
+    let _ = writeln!(out, "  |{ascii}|");
+}
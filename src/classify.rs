@@ -0,0 +1,365 @@
+//
+// classify.rs
+// BigDiff-rs
+//
+// Decides whether a file is diffed as text or copied as binary, from `--text`/`--binary` globs, magic numbers, known extensions and a sample of the head and tail of its contents.
+//
+// Thales Matheus Mendonça Santos - November 2025
+//
+// Text/binary classification with its reasons, so the choice can be explained in NOTE files.
+use std::fmt;
+use std::path::Path;
+
+use encoding_rs::Encoding;
+use glob::Pattern;
+
+use crate::encoding::TextEncoding;
+use crate::error::{Error, Result};
+use crate::utils::read_head_and_tail;
+
+// Bytes inspected at each end of the file.
+const SAMPLE_BYTES: u64 = 8192;
+
+// Share of control characters above which a sample is binary (1 in 20).
+const CONTROL_RATIO: usize = 20;
+
+/// Extensions that are always binary, whatever the contents look like.
+pub const BINARY_EXTENSIONS: &[&str] = &[
+    "7z", "a", "avi", "bin", "bmp", "bz2", "class", "dat", "db", "dll", "dmg", "doc", "docx",
+    "dylib", "ear", "eot", "exe", "flac", "gif", "gz", "ico", "iso", "jar", "jks", "jpeg", "jpg",
+    "lib", "m4a", "mkv", "mov", "mp3", "mp4", "o", "obj", "odp", "ods", "odt", "ogg", "otf", "p12",
+    "pdf", "pfx", "png", "ppt", "pptx", "psd", "pyc", "pyo", "rar", "so", "sqlite", "tar", "tgz",
+    "tif", "tiff", "ttf", "war", "wasm", "wav", "webm", "webp", "woff", "woff2", "xls", "xlsx",
+    "xz", "zip", "zst",
+];
+
+/// Extensions that are always text, whatever the contents look like.
+pub const TEXT_EXTENSIONS: &[&str] = &[
+    "bat",
+    "c",
+    "cc",
+    "cfg",
+    "cmake",
+    "conf",
+    "cpp",
+    "cs",
+    "css",
+    "csv",
+    "go",
+    "gradle",
+    "h",
+    "hpp",
+    "htm",
+    "html",
+    "ini",
+    "java",
+    "js",
+    "json",
+    "jsx",
+    "kt",
+    "log",
+    "md",
+    "mjs",
+    "php",
+    "pl",
+    "properties",
+    "ps1",
+    "psv",
+    "py",
+    "rb",
+    "rs",
+    "rst",
+    "scss",
+    "sh",
+    "sql",
+    "svg",
+    "swift",
+    "tex",
+    "toml",
+    "ts",
+    "tsv",
+    "tsx",
+    "txt",
+    "xml",
+    "yaml",
+    "yml",
+];
+
+// Signatures found at the start of common binary formats: (offset, bytes, format name).
+//
+// Each one includes a control or non-ASCII byte that text does not start with, so a text file
+// that happens to begin with a format's name ("RIFF", "OTTO", "ID3", ...) is not mistaken for
+// it. Formats whose signature is plain ASCII (GIF, PDF, RIFF, MP4) are left to their extension
+// and the content checks.
+const MAGIC: &[(usize, &[u8], &str)] = &[
+    (0, b"\x89PNG\r\n\x1a\n", "PNG"),
+    (0, b"\xFF\xD8\xFF", "JPEG"),
+    (0, b"PK\x03\x04", "zip"),
+    (0, b"PK\x05\x06", "zip"),
+    (0, b"\x1F\x8B", "gzip"),
+    (0, b"\xFD7zXZ\x00", "xz"),
+    (0, b"7z\xBC\xAF\x27\x1C", "7z"),
+    (0, b"\x28\xB5\x2F\xFD", "zstd"),
+    (0, b"Rar!\x1A\x07", "RAR"),
+    (257, b"ustar\x00", "tar"),
+    (257, b"ustar  \x00", "tar"),
+    (0, b"\x7FELF", "ELF"),
+    (0, b"\xCA\xFE\xBA\xBE", "Java class / Mach-O"),
+    (0, b"\xCF\xFA\xED\xFE", "Mach-O"),
+    (0, b"\xCE\xFA\xED\xFE", "Mach-O"),
+    (0, b"\x00asm", "WebAssembly"),
+    (0, b"SQLite format 3\x00", "SQLite"),
+    (0, b"OggS\x00", "Ogg"),
+    (0, b"fLaC\x00", "FLAC"),
+    (0, b"fLaC\x80", "FLAC"),
+    (0, b"ID3\x02", "MP3"),
+    (0, b"ID3\x03", "MP3"),
+    (0, b"ID3\x04", "MP3"),
+    (0, b"II*\x00", "TIFF"),
+    (0, b"MM\x00*", "TIFF"),
+    (0, b"wOFF\x00\x01\x00\x00", "WOFF"),
+    (0, b"wOF2\x00\x01\x00\x00", "WOFF2"),
+    (0, b"OTTO\x00", "OpenType"),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// Why a file was classified the way it was.
+pub enum Reason {
+    /// Matched a `--text` glob.
+    TextGlob,
+    /// Matched a `--binary` glob.
+    BinaryGlob,
+    /// Starts with the signature of a binary format.
+    Magic(&'static str),
+    /// Extension in [`TEXT_EXTENSIONS`].
+    TextExtension,
+    /// Extension in [`BINARY_EXTENSIONS`].
+    BinaryExtension,
+    /// Starts with a BOM or looks like UTF-16.
+    Encoding(TextEncoding),
+    /// Has `--encoding` set to an encoding the byte heuristics do not apply to.
+    EncodingOverride,
+    /// Holds NUL bytes.
+    NulBytes,
+    /// Too many control characters.
+    ControlCharacters,
+    /// No bytes at all.
+    Empty,
+    /// Could not be read; reading it again later reports the error.
+    Unreadable,
+    /// Valid UTF-8 (or single-byte text) with few control characters.
+    Content,
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reason::TextGlob => f.write_str("matches a --text pattern"),
+            Reason::BinaryGlob => f.write_str("matches a --binary pattern"),
+            Reason::Magic(format) => write!(f, "starts with the {format} signature"),
+            Reason::TextExtension => f.write_str("has a text extension"),
+            Reason::BinaryExtension => f.write_str("has a binary extension"),
+            Reason::Encoding(encoding) => write!(f, "is {encoding} text"),
+            Reason::EncodingOverride => f.write_str("has an --encoding override"),
+            Reason::NulBytes => f.write_str("contains NUL bytes"),
+            Reason::ControlCharacters => f.write_str("contains too many control characters"),
+            Reason::Empty => f.write_str("is empty"),
+            Reason::Unreadable => f.write_str("could not be read"),
+            Reason::Content => f.write_str("contents look like text"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// Outcome of [`Classifier::classify`].
+pub struct Classification {
+    pub is_text: bool,
+    pub reason: Reason,
+}
+
+impl Classification {
+    fn text(reason: Reason) -> Self {
+        Classification {
+            is_text: true,
+            reason,
+        }
+    }
+
+    fn binary(reason: Reason) -> Self {
+        Classification {
+            is_text: false,
+            reason,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+/// Text/binary classifier. Checks, first match wins: `--text` globs, `--binary` globs, magic
+/// numbers, known extensions, then the contents of the file's head and tail.
+pub struct Classifier {
+    text_globs: Vec<Pattern>,
+    binary_globs: Vec<Pattern>,
+}
+
+impl Classifier {
+    /// Compiles the `--text` and `--binary` globs.
+    pub fn new(text: &[String], binary: &[String]) -> Result<Self> {
+        let compile = |globs: &[String]| {
+            globs
+                .iter()
+                .map(|glob| {
+                    Pattern::new(glob).map_err(|source| Error::InvalidGlob {
+                        pattern: glob.clone(),
+                        source,
+                    })
+                })
+                .collect::<Result<Vec<_>>>()
+        };
+        Ok(Classifier {
+            text_globs: compile(text)?,
+            binary_globs: compile(binary)?,
+        })
+    }
+
+    /// The `--text` globs as given.
+    pub fn text_globs(&self) -> Vec<String> {
+        self.text_globs
+            .iter()
+            .map(|p| p.as_str().to_string())
+            .collect()
+    }
+
+    /// The `--binary` globs as given.
+    pub fn binary_globs(&self) -> Vec<String> {
+        self.binary_globs
+            .iter()
+            .map(|p| p.as_str().to_string())
+            .collect()
+    }
+
+    /// Classifies the file at `path`; `rel` is matched against the globs and `forced` is the
+    /// file's `--encoding` override, if any. Unreadable files count as binary.
+    pub fn classify(
+        &self,
+        path: &Path,
+        rel: &Path,
+        forced: Option<&'static Encoding>,
+    ) -> Classification {
+        let s_rel = rel.to_string_lossy().replace('\\', "/");
+        let name = rel.file_name().and_then(|s| s.to_str()).unwrap_or("");
+        let matches =
+            |globs: &[Pattern]| globs.iter().any(|p| p.matches(&s_rel) || p.matches(name));
+        if matches(&self.text_globs) {
+            return Classification::text(Reason::TextGlob);
+        }
+        if matches(&self.binary_globs) {
+            return Classification::binary(Reason::BinaryGlob);
+        }
+
+        let Some((head, tail)) = read_head_and_tail(path, SAMPLE_BYTES) else {
+            return Classification::binary(Reason::Unreadable);
+        };
+        if let Some(format) = magic(&head) {
+            return Classification::binary(Reason::Magic(format));
+        }
+
+        let extension = rel
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(str::to_ascii_lowercase);
+        if let Some(extension) = extension.as_deref() {
+            if TEXT_EXTENSIONS.contains(&extension) {
+                return Classification::text(Reason::TextExtension);
+            }
+            if BINARY_EXTENSIONS.contains(&extension) {
+                return Classification::binary(Reason::BinaryExtension);
+            }
+        }
+
+        classify_bytes(&head, tail.as_deref(), forced)
+    }
+}
+
+/// Content-only classification of a file's first bytes and, for large files, its last bytes.
+///
+/// The samples may cut a UTF-8 sequence at either end; that does not make them binary.
+pub fn classify_bytes(
+    head: &[u8],
+    tail: Option<&[u8]>,
+    forced: Option<&'static Encoding>,
+) -> Classification {
+    if head.is_empty() {
+        return Classification::text(Reason::Empty);
+    }
+    if let Some(encoding) = TextEncoding::sniff(head) {
+        return Classification::text(Reason::Encoding(encoding));
+    }
+    // NUL and control-character counts mean nothing for encodings such as UTF-16.
+    if forced.is_some_and(|encoding| !encoding.is_ascii_compatible()) {
+        return Classification::text(Reason::EncodingOverride);
+    }
+
+    let truncated = tail.is_some();
+    let samples = [
+        (head, false, truncated),
+        (tail.unwrap_or_default(), true, false),
+    ];
+    for (sample, cut_start, cut_end) in samples {
+        if sample.contains(&0) {
+            return Classification::binary(Reason::NulBytes);
+        }
+        let sample = if cut_start {
+            skip_continuation_bytes(sample)
+        } else {
+            sample
+        };
+        let single_byte = !is_utf8(sample, cut_end);
+        if control_characters(sample, single_byte) * CONTROL_RATIO > sample.len() {
+            return Classification::binary(Reason::ControlCharacters);
+        }
+    }
+    Classification::text(Reason::Content)
+}
+
+/// Name of the binary format whose signature starts `head`, if any.
+fn magic(head: &[u8]) -> Option<&'static str> {
+    MAGIC
+        .iter()
+        .find(|(offset, signature, _)| {
+            head.get(*offset..)
+                .is_some_and(|rest| rest.starts_with(signature))
+        })
+        .map(|(_, _, format)| *format)
+}
+
+// Valid UTF-8, allowing an incomplete sequence at the end when the sample was cut there.
+fn is_utf8(sample: &[u8], cut_end: bool) -> bool {
+    match std::str::from_utf8(sample) {
+        Ok(_) => true,
+        Err(err) => cut_end && err.error_len().is_none(),
+    }
+}
+
+// A tail sample can start in the middle of a UTF-8 sequence.
+fn skip_continuation_bytes(sample: &[u8]) -> &[u8] {
+    let skip = sample
+        .iter()
+        .take(3)
+        .take_while(|b| (**b & 0xC0) == 0x80)
+        .count();
+    &sample[skip..]
+}
+
+// C0 controls other than whitespace, backspace and escape (ANSI colors in logs) and DEL, plus
+// the bytes Windows-1252 leaves undefined when the sample is not UTF-8.
+fn control_characters(sample: &[u8], single_byte: bool) -> usize {
+    sample
+        .iter()
+        .filter(|b| match **b {
+            b'\t' | b'\n' | b'\r' | 0x08 | 0x0C | 0x1B => false,
+            0x00..=0x1F | 0x7F => true,
+            0x81 | 0x8D | 0x8F | 0x90 | 0x9D => single_byte,
+            _ => false,
+        })
+        .count()
+}
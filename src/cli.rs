@@ -9,23 +9,45 @@
 // Command-line argument parsing and option normalization.
 use std::path::PathBuf;
 
-use anyhow::{Context, Result};
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
 use glob::Pattern;
+use serde::{Deserialize, Serialize};
 
+use crate::archive::ArchiveLimits;
+use crate::classify::Classifier;
+use crate::comment::StyleRegistry;
+use crate::config::Config;
+use crate::encoding::EncodingRules;
+use crate::error::{Error, Result};
+use crate::sink::{OutputFormat, Preserve};
 use crate::utils::parse_size;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 pub struct Args {
-    /// Base directory (A)
-    pub base_dir: PathBuf,
+    #[command(subcommand)]
+    pub command: Option<Command>,
 
-    /// Target directory (B)
-    pub target_dir: PathBuf,
+    /// Base directory or archive (A)
+    #[arg(required = true)]
+    pub base_dir: Option<PathBuf>,
 
-    /// Output directory (Differences)
-    pub output_dir: PathBuf,
+    /// Target directory or archive (B)
+    #[arg(required = true)]
+    pub target_dir: Option<PathBuf>,
+
+    /// Output directory, or archive file with --output-format (Differences)
+    #[arg(required = true)]
+    pub output_dir: Option<PathBuf>,
+
+    /// TOML config file with custom comment styles and mappings
+    #[arg(short, long)]
+    pub config: Option<PathBuf>,
+
+    /// Write the output tree as a directory or stream it into a single archive
+    #[arg(long, value_enum, default_value_t = OutputFormat::Dir)]
+    pub output_format: OutputFormat,
 
     /// Glob patterns to ignore (can be repeated or comma separated)
     #[arg(short, long, value_delimiter = ',', num_args = 1..)]
@@ -35,13 +57,175 @@ pub struct Args {
     #[arg(short = 'E', long)]
     pub normalize_eol: bool,
 
+    /// Text encoding to assume instead of detecting it: LABEL for all files or GLOB=LABEL
+    /// (can be repeated; a BOM in the file always wins)
+    #[arg(long)]
+    pub encoding: Vec<String>,
+
+    /// Always diff files matching these globs as text (can be repeated or comma separated)
+    #[arg(long, value_delimiter = ',', num_args = 1..)]
+    pub text: Vec<String>,
+
+    /// Always treat files matching these globs as binary (can be repeated or comma separated)
+    #[arg(long, value_delimiter = ',', num_args = 1..)]
+    pub binary: Vec<String>,
+
     /// Max size (in bytes) for text diff per file (e.g., 5MB, 102400)
     #[arg(short = 'S', long, default_value = "5MB")]
     pub max_text_size: String,
 
-    /// Do not write anything; only print a summary of what would be done
+    /// Do not write anything; print every planned action and its destination
     #[arg(long)]
     pub dry_run: bool,
+
+    /// How `--dry-run` prints the plan
+    #[arg(long, value_enum, default_value_t = PlanFormat::Text, requires = "dry_run")]
+    pub plan_format: PlanFormat,
+
+    /// Record per-file errors in ERRORS.txt/ERRORS.json and continue; exit non-zero at the end
+    #[arg(long)]
+    pub keep_going: bool,
+
+    /// Continue an interrupted run in output_dir, keeping the outputs it already finished
+    #[arg(long)]
+    pub resume: bool,
+
+    /// What to do when output_dir already has content
+    #[arg(long, value_enum, default_value_t = OnExisting::Error)]
+    pub on_existing: OnExisting,
+
+    /// Source metadata to keep on .new/.deleted/.modified outputs (comma separated)
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub preserve: Vec<PreserveAttr>,
+
+    /// Compare JSON/YAML/TOML structurally; key order and formatting changes count as equal
+    #[arg(long)]
+    pub semantic: bool,
+
+    /// Expand nested archives (zip, jar, tar, tar.gz) found in both trees and diff their members
+    #[arg(long)]
+    pub recurse_archives: bool,
+
+    /// Canonicalize XML/SVG (attribute order, whitespace, namespace prefixes) before comparing
+    #[arg(long)]
+    pub canonical_xml: bool,
+
+    /// Key column(s) for row-level CSV/TSV comparison (can be repeated or comma separated)
+    #[arg(long = "csv-key", value_delimiter = ',', num_args = 1..)]
+    pub csv_key: Vec<String>,
+
+    /// Companion file written for sidecar-annotated formats (JSON, CSV, ...)
+    #[arg(long, value_enum, default_value_t = SidecarFormat::Diff)]
+    pub sidecar_format: SidecarFormat,
+
+    /// Skip the check that inline annotations keep block-comment files (HTML, CSS, ...) well
+    /// formed; by default a file that fails it gets a sidecar diff instead
+    #[arg(long)]
+    pub no_validate_annotations: bool,
+
+    /// Byte-level report (hashes, differing ranges, hex excerpts) for modified binary files
+    #[arg(long, value_enum, default_value_t = BinaryReport::Off)]
+    pub binary_report: BinaryReport,
+
+    /// Store modified binary files as a delta against the base when that is smaller
+    /// (rebuild them with `bigdiff apply`)
+    #[arg(long)]
+    pub binary_delta: bool,
+
+    /// Max size of each byte-level report (e.g., 64KB); further ranges are counted, not shown
+    #[arg(long, default_value = "64KB")]
+    pub binary_report_budget: String,
+
+    /// Largest file (either version) --binary-delta encodes (e.g., 256MB); larger ones are copied
+    #[arg(long, default_value = "256MB")]
+    pub binary_delta_max_size: String,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// Layout of the companion file produced by the sidecar strategy.
+pub enum SidecarFormat {
+    /// Unified diff in `<file>.modified.diff`
+    #[default]
+    Diff,
+    /// JSON hunk list in `<file>.modified.hunks.json`
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// Where the byte-level report of a modified binary file goes.
+pub enum BinaryReport {
+    /// No report; the NOTE only gives sizes and the classification
+    #[default]
+    Off,
+    /// Append the report to `<file>.modified.NOTE.txt`
+    Note,
+    /// Write the report to `<file>.modified.hexdiff`
+    Hexdiff,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// Policy for an output directory (or archive file) that already exists.
+pub enum OnExisting {
+    /// Refuse to run unless the directory is empty
+    #[default]
+    Error,
+    /// Remove the files a previous run listed in bigdiff-manifest.json, then write (dir output only)
+    Clean,
+    /// Keep existing files; fail before writing if an output would replace one (dir output only)
+    Merge,
+    /// Replace same-named files and leave everything else
+    Overwrite,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+/// One attribute accepted by `--preserve`.
+pub enum PreserveAttr {
+    /// Permission bits
+    Mode,
+    /// Modification time
+    Mtime,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+/// Presentation of the `--dry-run` plan.
+pub enum PlanFormat {
+    /// One line per action followed by its outputs
+    #[default]
+    Text,
+    /// A single JSON document with the counters and every action
+    Json,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Inspect the comment style table
+    Styles {
+        #[command(subcommand)]
+        action: StylesAction,
+    },
+    /// Rebuild targets from their base and the `.bdelta` files written by --binary-delta: one
+    /// file, or every delta listed in an output folder's manifest
+    Apply {
+        /// Base file (A) the delta was made against, or the base folder
+        base: PathBuf,
+        /// Delta file (`<name>.modified.bdelta`), or the output folder of a --binary-delta run
+        delta: PathBuf,
+        /// File to create with the rebuilt target (B), or the folder to rebuild the targets into
+        output: PathBuf,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum StylesAction {
+    /// Print the effective style table (built-ins plus config overrides)
+    List {
+        /// TOML config file with custom comment styles and mappings
+        #[arg(short, long)]
+        config: Option<PathBuf>,
+    },
 }
 
 #[derive(Debug)]
@@ -50,7 +234,97 @@ pub struct Options {
     pub normalize_eol: bool,
     pub max_text_size: u64,
     pub ignore_patterns: Vec<Pattern>,
+    pub encodings: EncodingRules,
+    pub classifier: Classifier,
     pub dry_run: bool,
+    pub keep_going: bool,
+    pub resume: bool,
+    pub on_existing: OnExisting,
+    pub preserve: Preserve,
+    pub output_format: OutputFormat,
+    pub validate_annotations: bool,
+    pub sidecar_format: SidecarFormat,
+    pub binary_report: BinaryReport,
+    pub binary_report_budget: u64,
+    pub binary_delta: bool,
+    pub binary_delta_max_size: u64,
+    pub semantic: bool,
+    pub canonical_xml: bool,
+    pub recurse_archives: bool,
+    pub archive_limits: ArchiveLimits,
+    pub csv_keys: Vec<String>,
+    pub styles: StyleRegistry,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            normalize_eol: false,
+            max_text_size: parse_size("5MB"),
+            ignore_patterns: Vec::new(),
+            encodings: EncodingRules::default(),
+            classifier: Classifier::default(),
+            dry_run: false,
+            keep_going: false,
+            resume: false,
+            on_existing: OnExisting::default(),
+            preserve: Preserve::default(),
+            output_format: OutputFormat::default(),
+            validate_annotations: true,
+            sidecar_format: SidecarFormat::default(),
+            binary_report: BinaryReport::default(),
+            binary_report_budget: parse_size("64KB"),
+            binary_delta: false,
+            binary_delta_max_size: parse_size("256MB"),
+            semantic: false,
+            canonical_xml: false,
+            recurse_archives: false,
+            archive_limits: ArchiveLimits::default(),
+            csv_keys: Vec::new(),
+            styles: StyleRegistry::builtin(),
+        }
+    }
+}
+
+impl Options {
+    /// Rejects options that the chosen output format cannot honor.
+    ///
+    /// An archive output is always written whole, so it can only be refused or replaced, and
+    /// its entries get fixed metadata.
+    pub fn check_output_format(&self) -> Result<()> {
+        if self.output_format == OutputFormat::Dir {
+            return Ok(());
+        }
+        let option = if matches!(self.on_existing, OnExisting::Clean | OnExisting::Merge) {
+            format!("--on-existing={}", value_name(self.on_existing))
+        } else if self.resume {
+            "--resume".to_string()
+        } else if self.preserve.any() {
+            "--preserve".to_string()
+        } else {
+            return Ok(());
+        };
+        Err(Error::UnsupportedOutputOption {
+            option,
+            format: value_name(self.output_format),
+        })
+    }
+}
+
+// The spelling clap accepts for `value`, for messages that point back at the CLI.
+fn value_name(value: impl ValueEnum) -> String {
+    value
+        .to_possible_value()
+        .map(|v| v.get_name().to_string())
+        .unwrap_or_default()
+}
+
+/// Builds the style registry from an optional config file.
+pub fn load_styles(config: Option<&PathBuf>) -> Result<StyleRegistry> {
+    match config {
+        Some(path) => StyleRegistry::from_config(&Config::load(path)?),
+        None => Ok(StyleRegistry::builtin()),
+    }
 }
 
 pub fn build_options(args: &Args) -> Result<Options> {
@@ -58,7 +332,12 @@ pub fn build_options(args: &Args) -> Result<Options> {
     let patterns = args
         .ignore
         .iter()
-        .map(|s| Pattern::new(s).with_context(|| format!("Invalid glob pattern: {s}")))
+        .map(|s| {
+            Pattern::new(s).map_err(|source| Error::InvalidGlob {
+                pattern: s.clone(),
+                source,
+            })
+        })
         .collect::<Result<Vec<_>>>()?;
 
     // Translate raw CLI values into strongly typed options for the rest of the app.
@@ -66,6 +345,28 @@ pub fn build_options(args: &Args) -> Result<Options> {
         normalize_eol: args.normalize_eol,
         max_text_size: parse_size(&args.max_text_size),
         ignore_patterns: patterns,
+        encodings: EncodingRules::parse(&args.encoding)?,
+        classifier: Classifier::new(&args.text, &args.binary)?,
         dry_run: args.dry_run,
+        keep_going: args.keep_going,
+        resume: args.resume,
+        on_existing: args.on_existing,
+        preserve: Preserve {
+            mode: args.preserve.contains(&PreserveAttr::Mode),
+            mtime: args.preserve.contains(&PreserveAttr::Mtime),
+        },
+        output_format: args.output_format,
+        validate_annotations: !args.no_validate_annotations,
+        sidecar_format: args.sidecar_format,
+        binary_report: args.binary_report,
+        binary_report_budget: parse_size(&args.binary_report_budget),
+        binary_delta: args.binary_delta,
+        binary_delta_max_size: parse_size(&args.binary_delta_max_size),
+        semantic: args.semantic,
+        canonical_xml: args.canonical_xml,
+        recurse_archives: args.recurse_archives,
+        archive_limits: ArchiveLimits::default(),
+        csv_keys: args.csv_key.clone(),
+        styles: load_styles(args.config.as_ref())?,
     })
 }
@@ -7,9 +7,18 @@
 // Thales Matheus Mendonça Santos - November 2025
 //
 // Helpers that add deletion/new annotations tailored to the file's comment syntax.
+use std::collections::BTreeMap;
+use std::fmt;
 use std::path::Path;
+use std::sync::OnceLock;
 
-#[derive(Debug)]
+use glob::Pattern;
+
+use crate::config::{Config, StyleDef};
+use crate::detect::sniff_file;
+use crate::error::{Error, Result};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 /// Represents how a given language denotes comments.
 pub enum CommentStyle {
     LinePrefix {
@@ -21,19 +30,57 @@ pub enum CommentStyle {
         close: String,
         new_block: String,
     },
+    /// For formats without comments (JSON, CSV): the target is copied verbatim and the
+    /// changes go to a companion `.modified.diff` file instead of inline markers.
+    Sidecar,
 }
 
 impl CommentStyle {
+    fn line(prefix: &str) -> Self {
+        CommentStyle::LinePrefix {
+            prefix: format!("{prefix} "),
+            new_suffix: format!(" {prefix} NEW"),
+        }
+    }
+
+    fn block(open: &str, close: &str) -> Self {
+        CommentStyle::Block {
+            open: open.into(),
+            close: close.into(),
+            new_block: format!("{open} NEW {close}"),
+        }
+    }
+
+    /// Whether changes are written inline or to a companion diff file.
+    pub fn is_sidecar(&self) -> bool {
+        matches!(self, CommentStyle::Sidecar)
+    }
+
     /// Marks a line as deleted using the appropriate comment decoration.
     pub fn deleted_line(&self, line: &str) -> String {
+        match self {
+            CommentStyle::Sidecar => {
+                let (content, end) = split_newline(line);
+                format!("-{content}{end}")
+            }
+            _ => self.comment_line("DELETED", line),
+        }
+    }
+
+    /// Wraps `line` in a comment tagged with `label` (e.g. `# DELETED: ...`).
+    ///
+    /// Sidecar styles have no comment syntax, so the label is emitted as plain text.
+    pub fn comment_line(&self, label: &str, line: &str) -> String {
         let (content, end) = split_newline(line);
         match self {
             CommentStyle::LinePrefix { prefix, .. } => {
-                format!("{prefix}DELETED: {content}{end}")
-            }
-            CommentStyle::Block { open, close, .. } => {
-                format!("{open} DELETED: {content} {close}{end}")
+                format!("{prefix}{label}: {content}{end}")
             }
+            CommentStyle::Block { open, close, .. } => match escape_block_content(content, close) {
+                Some(escaped) => format!("{open} {label} (escaped): {escaped} {close}{end}"),
+                None => format!("{open} {label}: {content} {close}{end}"),
+            },
+            CommentStyle::Sidecar => format!("{label}: {content}{end}"),
         }
     }
 
@@ -45,8 +92,71 @@ impl CommentStyle {
                 format!("{content}{new_suffix}{end}")
             }
             CommentStyle::Block { new_block, .. } => format!("{content} {new_block}{end}"),
+            CommentStyle::Sidecar => format!("+{content}{end}"),
+        }
+    }
+}
+
+/// Checks that annotating `original` into `annotated` only added comments.
+///
+/// Block comments are stripped from both texts and the remaining code is compared ignoring
+/// whitespace; a mismatch means an annotation opened or closed a comment it should not have.
+/// Line-prefix and sidecar styles always pass.
+pub fn validate_annotation(style: &CommentStyle, original: &str, annotated: &str) -> bool {
+    match style {
+        CommentStyle::LinePrefix { .. } | CommentStyle::Sidecar => true,
+        CommentStyle::Block { open, close, .. } => {
+            let code = |text: &str| -> String {
+                strip_block_comments(text, open, close)
+                    .chars()
+                    .filter(|c| !c.is_whitespace())
+                    .collect()
+            };
+            code(original) == code(annotated)
+        }
+    }
+}
+
+fn strip_block_comments(text: &str, open: &str, close: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(open) {
+        out.push_str(&rest[..start]);
+        let body = &rest[start + open.len()..];
+        match body.find(close) {
+            Some(end) => rest = &body[end + close.len()..],
+            // An unterminated comment swallows the rest of the file.
+            None => return out,
         }
     }
+    out.push_str(rest);
+    out
+}
+
+// Deleted content that would terminate the wrapping comment early is escaped by breaking up the
+// closer's leading pair (`*/` -> `*\/`, `--` -> `-\-`, which also keeps XML comments valid).
+// Backslashes are doubled so the original text stays recoverable.
+fn escape_block_content(content: &str, close: &str) -> Option<String> {
+    let mut lead = close.chars();
+    let (first, second) = (lead.next()?, lead.next()?);
+    let pair: String = [first, second].iter().collect();
+    if !content.contains(&pair) {
+        return None;
+    }
+
+    let mut escaped = String::with_capacity(content.len() + 8);
+    let mut chars = content.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            escaped.push_str("\\\\");
+            continue;
+        }
+        escaped.push(c);
+        if c == first && chars.peek() == Some(&second) {
+            escaped.push('\\');
+        }
+    }
+    Some(escaped)
 }
 
 // Preserve existing trailing newline so diff output stays structurally correct.
@@ -58,93 +168,432 @@ fn split_newline(s: &str) -> (&str, &str) {
     }
 }
 
-pub fn comment_style_for(path: &Path) -> CommentStyle {
-    let ext = path
-        .extension()
-        .and_then(|s| s.to_str())
-        .unwrap_or("")
-        .to_lowercase();
-    let ext = format!(".{ext}");
-
-    let slash_exts = [
-        ".c", ".h", ".cpp", ".hpp", ".cc", ".java", ".js", ".ts", ".tsx", ".cs", ".swift", ".go",
-        ".kt", ".kts", ".scala", ".dart", ".php", ".rs",
-    ];
-    let hash_exts = [
-        ".py",
-        ".sh",
-        ".rb",
-        ".r",
-        ".ps1",
-        ".toml",
-        ".yaml",
-        ".yml",
-        ".cfg",
-        ".gitignore",
-        ".dockerignore",
-    ];
-    let dash_exts = [".sql", ".hs", ".lua"];
-    let percent_exts = [".tex", ".m"];
-    let semicolon_exts = [".ini"];
-    let csv_like = [".csv", ".tsv"];
-    let text_like = [".txt", ".log", ".conf", ".md"];
-
-    let html_exts = [".html", ".htm", ".xml", ".xhtml", ".svg"];
-    let cblock_exts = [".css", ".scss", ".less"];
-    let json_exts = [".json"];
-
-    if slash_exts.contains(&ext.as_str()) {
-        return CommentStyle::LinePrefix {
-            prefix: "// ".into(),
-            new_suffix: " // NEW".into(),
-        };
+impl fmt::Display for CommentStyle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommentStyle::LinePrefix { prefix, new_suffix } => {
+                write!(f, "{prefix}DELETED: ... | ...{new_suffix}")
+            }
+            CommentStyle::Block {
+                open,
+                close,
+                new_block,
+            } => write!(f, "{open} DELETED: ... {close} | ... {new_block}"),
+            CommentStyle::Sidecar => f.write_str("(unchanged target + .modified.diff)"),
+        }
     }
-    if hash_exts.contains(&ext.as_str())
-        || text_like.contains(&ext.as_str())
-        || csv_like.contains(&ext.as_str())
-    {
-        return CommentStyle::LinePrefix {
-            prefix: "# ".into(),
-            new_suffix: " # NEW".into(),
+}
+
+/// How a registry rule matches a path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MatchKind {
+    Filename,
+    Glob,
+    Extension,
+    Language,
+    Default,
+}
+
+impl fmt::Display for MatchKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            MatchKind::Filename => "filename",
+            MatchKind::Glob => "glob",
+            MatchKind::Extension => "extension",
+            MatchKind::Language => "language",
+            MatchKind::Default => "default",
         };
+        f.write_str(s)
     }
-    if dash_exts.contains(&ext.as_str()) {
-        return CommentStyle::LinePrefix {
-            prefix: "-- ".into(),
-            new_suffix: " -- NEW".into(),
+}
+
+/// One row of the effective style table, as printed by `bigdiff styles list`.
+#[derive(Debug, Clone)]
+pub struct StyleEntry {
+    pub kind: MatchKind,
+    pub pattern: String,
+    pub style: String,
+    pub builtin: bool,
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+    style: String,
+    builtin: bool,
+}
+
+#[derive(Debug, Clone)]
+/// Data-driven table mapping file names, globs and extensions to named comment styles.
+pub struct StyleRegistry {
+    styles: BTreeMap<String, CommentStyle>,
+    filenames: BTreeMap<String, Rule>,
+    globs: Vec<(Pattern, Rule)>,
+    extensions: BTreeMap<String, Rule>,
+    languages: BTreeMap<String, String>,
+    default: Rule,
+}
+
+const HASH_EXTS: &[&str] = &[
+    "py",
+    "sh",
+    "bash",
+    "zsh",
+    "rb",
+    "r",
+    "ps1",
+    "pl",
+    "toml",
+    "yaml",
+    "yml",
+    "cfg",
+    "mk",
+    "cmake",
+    "txt",
+    "log",
+    "conf",
+    "md",
+    "awk",
+    "tcl",
+    "dockerfile",
+];
+const SLASH_EXTS: &[&str] = &[
+    "c", "h", "cpp", "hpp", "cc", "java", "js", "mjs", "cjs", "jsx", "ts", "tsx", "cs", "swift",
+    "go", "kt", "kts", "scala", "dart", "php", "rs", "groovy", "gradle",
+];
+const DASH_EXTS: &[&str] = &["sql", "hs", "lua"];
+const PERCENT_EXTS: &[&str] = &["tex", "m", "erl", "hrl"];
+const SEMICOLON_EXTS: &[&str] = &[
+    "ini", "clj", "cljs", "cljc", "edn", "el", "lisp", "scm", "asm",
+];
+const REM_EXTS: &[&str] = &["bat", "cmd"];
+const QUOTE_EXTS: &[&str] = &["vim"];
+const HTML_EXTS: &[&str] = &["html", "htm", "xml", "xhtml", "svg", "vue"];
+const CBLOCK_EXTS: &[&str] = &["css", "scss", "less"];
+const SIDECAR_EXTS: &[&str] = &[
+    "json", "jsonl", "ndjson", "geojson", "ipynb", "csv", "tsv", "psv",
+];
+
+// Built-in styles and the extensions they cover; the first entry is the fallback for unknown types.
+const BUILTIN_EXTENSIONS: &[(&str, &[&str])] = &[
+    ("hash", HASH_EXTS),
+    ("slash", SLASH_EXTS),
+    ("dash", DASH_EXTS),
+    ("percent", PERCENT_EXTS),
+    ("semicolon", SEMICOLON_EXTS),
+    ("rem", REM_EXTS),
+    ("quote", QUOTE_EXTS),
+    ("html", HTML_EXTS),
+    ("cblock", CBLOCK_EXTS),
+    ("sidecar", SIDECAR_EXTS),
+];
+
+const BUILTIN_FILENAMES: &[(&str, &str)] = &[
+    (".gitignore", "hash"),
+    (".dockerignore", "hash"),
+    (".editorconfig", "hash"),
+    (".bashrc", "hash"),
+    (".zshrc", "hash"),
+    (".profile", "hash"),
+    ("Dockerfile", "hash"),
+    ("Containerfile", "hash"),
+    ("Makefile", "hash"),
+    ("GNUmakefile", "hash"),
+    ("makefile", "hash"),
+    ("CMakeLists.txt", "hash"),
+    ("Gemfile", "hash"),
+    ("Rakefile", "hash"),
+    ("Vagrantfile", "hash"),
+    ("Pipfile", "hash"),
+    ("Jenkinsfile", "slash"),
+];
+
+const BUILTIN_GLOBS: &[(&str, &str)] = &[
+    ("Dockerfile.*", "hash"),
+    ("*.Dockerfile", "hash"),
+    ("Makefile.*", "hash"),
+    ("Jenkinsfile.*", "slash"),
+];
+
+// Interpreter (shebang) and editor mode (modeline) names that differ from the extension they imply.
+// Names not listed here are looked up as extensions directly (e.g. `lua`, `sql`, `php`).
+const BUILTIN_LANGUAGES: &[(&str, &str)] = &[
+    ("bash", "sh"),
+    ("zsh", "sh"),
+    ("ksh", "sh"),
+    ("dash", "sh"),
+    ("ash", "sh"),
+    ("shell-script", "sh"),
+    ("python", "py"),
+    ("pypy", "py"),
+    ("ruby", "rb"),
+    ("perl", "pl"),
+    ("cperl", "pl"),
+    ("node", "js"),
+    ("nodejs", "js"),
+    ("deno", "js"),
+    ("bun", "js"),
+    ("javascript", "js"),
+    ("typescript", "ts"),
+    ("ts-node", "ts"),
+    ("rscript", "r"),
+    ("pwsh", "ps1"),
+    ("powershell", "ps1"),
+    ("gawk", "awk"),
+    ("tclsh", "tcl"),
+    ("make", "mk"),
+    ("makefile", "mk"),
+    ("dockerfile", "dockerfile"),
+    ("c++", "cpp"),
+    ("csharp", "cs"),
+    ("rust", "rs"),
+    ("kotlin", "kt"),
+    ("haskell", "hs"),
+    ("erlang", "erl"),
+    ("clojure", "clj"),
+    ("emacs-lisp", "el"),
+    ("latex", "tex"),
+    ("matlab", "m"),
+    ("dosbatch", "bat"),
+    ("dosini", "ini"),
+    ("markdown", "md"),
+    ("nxml", "xml"),
+    ("yaml", "yml"),
+];
+
+fn builtin_styles() -> BTreeMap<String, CommentStyle> {
+    let mut styles = BTreeMap::new();
+    styles.insert("hash".into(), CommentStyle::line("#"));
+    styles.insert("slash".into(), CommentStyle::line("//"));
+    styles.insert("dash".into(), CommentStyle::line("--"));
+    styles.insert("percent".into(), CommentStyle::line("%"));
+    styles.insert("semicolon".into(), CommentStyle::line(";"));
+    styles.insert("quote".into(), CommentStyle::line("\""));
+    // `REM` only works at the start of a command, so new lines chain a second command.
+    styles.insert(
+        "rem".into(),
+        CommentStyle::LinePrefix {
+            prefix: "REM ".into(),
+            new_suffix: " & REM NEW".into(),
+        },
+    );
+    styles.insert("html".into(), CommentStyle::block("<!--", "-->"));
+    styles.insert("cblock".into(), CommentStyle::block("/*", "*/"));
+    styles.insert("sidecar".into(), CommentStyle::Sidecar);
+    styles
+}
+
+fn normalize_ext(ext: &str) -> String {
+    ext.trim_start_matches('.').to_lowercase()
+}
+
+impl StyleRegistry {
+    /// The mapping BigDiff ships with, before any user configuration is applied.
+    pub fn builtin() -> Self {
+        let builtin = |style: &str| Rule {
+            style: style.into(),
+            builtin: true,
         };
+        let mut extensions = BTreeMap::new();
+        for (style, exts) in BUILTIN_EXTENSIONS {
+            for ext in *exts {
+                extensions.insert((*ext).to_string(), builtin(style));
+            }
+        }
+        let filenames = BUILTIN_FILENAMES
+            .iter()
+            .map(|(name, style)| ((*name).to_string(), builtin(style)))
+            .collect();
+        let globs = BUILTIN_GLOBS
+            .iter()
+            .map(|(pat, style)| {
+                let pattern = Pattern::new(pat).expect("built-in style globs are valid");
+                (pattern, builtin(style))
+            })
+            .collect();
+        let languages = BUILTIN_LANGUAGES
+            .iter()
+            .map(|(name, ext)| ((*name).to_string(), (*ext).to_string()))
+            .collect();
+
+        StyleRegistry {
+            styles: builtin_styles(),
+            filenames,
+            globs,
+            extensions,
+            languages,
+            default: builtin(BUILTIN_EXTENSIONS[0].0),
+        }
     }
-    if percent_exts.contains(&ext.as_str()) {
-        return CommentStyle::LinePrefix {
-            prefix: "% ".into(),
-            new_suffix: " % NEW".into(),
+
+    /// Builds the built-in registry and layers the user's style definitions and mappings on top.
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let mut registry = Self::builtin();
+
+        for (name, def) in &config.styles {
+            registry
+                .styles
+                .insert(name.clone(), style_from_def(name, def)?);
+        }
+
+        let user_rule = |style: &String| -> Result<Rule> {
+            if !registry.styles.contains_key(style) {
+                return Err(Error::UnknownStyle {
+                    name: style.clone(),
+                });
+            }
+            Ok(Rule {
+                style: style.clone(),
+                builtin: false,
+            })
         };
+
+        let mut filenames = Vec::new();
+        for (name, style) in &config.filenames {
+            filenames.push((name.clone(), user_rule(style)?));
+        }
+        let mut globs = Vec::new();
+        for (pat, style) in &config.globs {
+            let pattern = Pattern::new(pat).map_err(|source| Error::InvalidGlob {
+                pattern: pat.clone(),
+                source,
+            })?;
+            globs.push((pattern, user_rule(style)?));
+        }
+        let mut extensions = Vec::new();
+        for (ext, style) in &config.extensions {
+            extensions.push((normalize_ext(ext), user_rule(style)?));
+        }
+        let default = config.default_style.as_ref().map(user_rule).transpose()?;
+
+        registry.filenames.extend(filenames);
+        // User globs are tried before the built-in ones.
+        globs.append(&mut registry.globs);
+        registry.globs = globs;
+        registry.extensions.extend(extensions);
+        if let Some(default) = default {
+            registry.default = default;
+        }
+        Ok(registry)
     }
-    if semicolon_exts.contains(&ext.as_str()) {
-        return CommentStyle::LinePrefix {
-            prefix: "; ".into(),
-            new_suffix: " ; NEW".into(),
-        };
+
+    /// Resolves the style from the path alone: exact filename, then globs, then extension.
+    pub fn style_for(&self, path: &Path) -> &CommentStyle {
+        let rule = self
+            .rule_for_name(path)
+            .or_else(|| self.rule_for_extension(path))
+            .unwrap_or(&self.default);
+        &self.styles[&rule.style]
     }
 
-    if html_exts.contains(&ext.as_str()) {
-        return CommentStyle::Block {
-            open: "<!--".into(),
-            close: "-->".into(),
-            new_block: "<!-- NEW -->".into(),
-        };
+    /// Resolves the style for a file on disk, also reading its shebang and editor modelines.
+    ///
+    /// Precedence: filename/glob, modeline, extension, shebang, then the default style.
+    pub fn style_for_file(&self, rel: &Path, abs: &Path) -> &CommentStyle {
+        if let Some(rule) = self.rule_for_name(rel) {
+            return &self.styles[&rule.style];
+        }
+
+        let hints = sniff_file(abs);
+        let rule = hints
+            .modeline
+            .as_deref()
+            .and_then(|lang| self.rule_for_language(lang))
+            .or_else(|| self.rule_for_extension(rel))
+            .or_else(|| {
+                hints
+                    .shebang
+                    .as_deref()
+                    .and_then(|lang| self.rule_for_language(lang))
+            })
+            .unwrap_or(&self.default);
+        &self.styles[&rule.style]
+    }
+
+    fn rule_for_name(&self, path: &Path) -> Option<&Rule> {
+        let name = path.file_name().and_then(|s| s.to_str()).unwrap_or("");
+        if let Some(rule) = self.filenames.get(name) {
+            return Some(rule);
+        }
+
+        let s_rel = path.to_string_lossy().replace('\\', "/");
+        self.globs
+            .iter()
+            .find(|(pat, _)| pat.matches(name) || pat.matches(&s_rel))
+            .map(|(_, rule)| rule)
+    }
+
+    fn rule_for_extension(&self, path: &Path) -> Option<&Rule> {
+        let ext = path.extension().and_then(|s| s.to_str())?;
+        self.extensions.get(&normalize_ext(ext))
+    }
+
+    // Language names come from shebangs and modelines; unknown names are tried as extensions.
+    fn rule_for_language(&self, lang: &str) -> Option<&Rule> {
+        let ext = self.languages.get(lang).map(String::as_str).unwrap_or(lang);
+        self.extensions.get(ext)
     }
-    if cblock_exts.contains(&ext.as_str()) || json_exts.contains(&ext.as_str()) {
-        return CommentStyle::Block {
-            open: "/*".into(),
-            close: "*/".into(),
-            new_block: "/* NEW */".into(),
+
+    /// Looks up a named style (built-in or user-defined).
+    pub fn style(&self, name: &str) -> Option<&CommentStyle> {
+        self.styles.get(name)
+    }
+
+    /// Lists every rule in resolution order, for display.
+    pub fn entries(&self) -> Vec<StyleEntry> {
+        let entry = |kind, pattern: String, rule: &Rule| StyleEntry {
+            kind,
+            pattern,
+            style: rule.style.clone(),
+            builtin: rule.builtin,
         };
+        let mut entries = Vec::new();
+        for (name, rule) in &self.filenames {
+            entries.push(entry(MatchKind::Filename, name.clone(), rule));
+        }
+        for (pat, rule) in &self.globs {
+            entries.push(entry(MatchKind::Glob, pat.as_str().to_string(), rule));
+        }
+        for (ext, rule) in &self.extensions {
+            entries.push(entry(MatchKind::Extension, format!(".{ext}"), rule));
+        }
+        for (lang, ext) in &self.languages {
+            if let Some(rule) = self.extensions.get(ext) {
+                entries.push(entry(MatchKind::Language, lang.clone(), rule));
+            }
+        }
+        entries.push(entry(MatchKind::Default, "*".into(), &self.default));
+        entries
     }
+}
 
-    // Default to hash comments for unknown extensions (safe for plain text).
-    CommentStyle::LinePrefix {
-        prefix: "# ".into(),
-        new_suffix: " # NEW".into(),
+fn style_from_def(name: &str, def: &StyleDef) -> Result<CommentStyle> {
+    match (&def.prefix, &def.open, &def.close) {
+        (Some(prefix), None, None) => Ok(CommentStyle::LinePrefix {
+            prefix: prefix.clone(),
+            new_suffix: def
+                .new_suffix
+                .clone()
+                .unwrap_or_else(|| format!(" {} NEW", prefix.trim_end())),
+        }),
+        (None, Some(open), Some(close)) => Ok(CommentStyle::Block {
+            open: open.clone(),
+            close: close.clone(),
+            new_block: def
+                .new_block
+                .clone()
+                .unwrap_or_else(|| format!("{open} NEW {close}")),
+        }),
+        _ => Err(Error::InvalidStyle {
+            name: name.to_string(),
+        }),
     }
 }
+
+/// Resolves a style using only the built-in registry.
+pub fn comment_style_for(path: &Path) -> CommentStyle {
+    static BUILTIN: OnceLock<StyleRegistry> = OnceLock::new();
+    BUILTIN
+        .get_or_init(StyleRegistry::builtin)
+        .style_for(path)
+        .clone()
+}
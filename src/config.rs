@@ -0,0 +1,85 @@
+//
+// config.rs
+// BigDiff-rs
+//
+// Loads the optional TOML configuration file that lets users extend or override BigDiff's built-in tables.
+//
+// Thales Matheus Mendonça Santos - November 2025
+//
+// User configuration: comment style definitions and the mappings that select them.
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use serde::de::{MapAccess, Visitor};
+use serde::{Deserialize, Deserializer};
+
+use crate::error::{Error, Result};
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+/// Top-level layout of a `bigdiff.toml` file.
+pub struct Config {
+    /// Extra named comment styles, or redefinitions of built-in ones.
+    pub styles: BTreeMap<String, StyleDef>,
+    /// Extension (with or without leading dot) -> style name.
+    pub extensions: BTreeMap<String, String>,
+    /// Exact file name (e.g. `Makefile`) -> style name.
+    pub filenames: BTreeMap<String, String>,
+    /// Glob matched against the file name or relative path -> style name, in file order.
+    /// The first glob that matches wins.
+    #[serde(deserialize_with = "ordered_table")]
+    pub globs: Vec<(String, String)>,
+    /// Style used when nothing else matches.
+    pub default_style: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+/// A comment style: either a line `prefix` or an `open`/`close` block pair.
+pub struct StyleDef {
+    pub prefix: Option<String>,
+    pub new_suffix: Option<String>,
+    pub open: Option<String>,
+    pub close: Option<String>,
+    pub new_block: Option<String>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self> {
+        let raw = fs::read_to_string(path).map_err(Error::io(path))?;
+        toml::from_str(&raw).map_err(|source| Error::Config {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+}
+
+// Keeps a table's entries in the order they are written, for first-match-wins lookups.
+fn ordered_table<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Vec<(String, String)>, D::Error> {
+    struct Entries;
+
+    impl<'de> Visitor<'de> for Entries {
+        type Value = Vec<(String, String)>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a table of pattern = style entries")
+        }
+
+        fn visit_map<A: MapAccess<'de>>(
+            self,
+            mut map: A,
+        ) -> std::result::Result<Self::Value, A::Error> {
+            let mut entries = Vec::new();
+            while let Some(entry) = map.next_entry()? {
+                entries.push(entry);
+            }
+            Ok(entries)
+        }
+    }
+
+    deserializer.deserialize_map(Entries)
+}
@@ -0,0 +1,300 @@
+//
+// delta.rs
+// BigDiff-rs
+//
+// Self-contained binary delta format: a modified binary is stored as copy/insert instructions against the base, together with the base and target hashes, and `bigdiff apply` rebuilds the target from it.
+//
+// Thales Matheus Mendonça Santos - November 2025
+//
+// Binary delta encoding (`--binary-delta`) and decoding (`bigdiff apply`).
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+use crate::error::{DeltaError, Error, Result};
+use crate::manifest::{check_relative, Manifest, MANIFEST_FILE};
+use crate::naming::DELTA_SUFFIX;
+use crate::utils::ensure_output_target_safe;
+
+/// First bytes of every delta file; the last one is the format version.
+pub const DELTA_MAGIC: &[u8] = b"BIGDIFF-DELTA\x01";
+
+// Instruction tags.
+const OP_END: u8 = 0;
+const OP_COPY: u8 = 1;
+const OP_ADD: u8 = 2;
+
+// Smallest match worth a copy instruction; bases are indexed every this many bytes.
+const MIN_BLOCK: usize = 16;
+// Upper bound on indexed blocks, so the index stays small for large bases.
+const MAX_INDEXED_BLOCKS: usize = 1 << 20;
+// Multiplier of the rolling hash.
+const PRIME: u32 = 0x0100_0193;
+
+/// Base and target identity recorded at the start of a delta.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeltaHeader {
+    pub base_size: u64,
+    pub base_sha256: [u8; 32],
+    pub target_size: u64,
+    pub target_sha256: [u8; 32],
+}
+
+/// Encodes `target` as a delta against `base`.
+///
+/// Layout: [`DELTA_MAGIC`], base size (u64 LE), base SHA-256, target size (u64 LE), target
+/// SHA-256, then instructions: `1, offset, len` copies from the base, `2, len, bytes` inserts
+/// literal bytes, `0` ends. Numbers inside instructions are LEB128 varints.
+pub fn encode(base: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut out = DELTA_MAGIC.to_vec();
+    out.extend((base.len() as u64).to_le_bytes());
+    out.extend(Sha256::digest(base));
+    out.extend((target.len() as u64).to_le_bytes());
+    out.extend(Sha256::digest(target));
+
+    let block = (base.len() / MAX_INDEXED_BLOCKS).max(MIN_BLOCK);
+    let mut index: HashMap<u32, usize> = HashMap::new();
+    for offset in (0..base.len().saturating_sub(block - 1)).step_by(block) {
+        index
+            .entry(hash(&base[offset..offset + block]))
+            .or_insert(offset);
+    }
+    // Highest power of the multiplier in a window, to roll the oldest byte out.
+    let top = (1..block).fold(1u32, |acc, _| acc.wrapping_mul(PRIME));
+
+    let (mut pos, mut literal) = (0, 0);
+    let mut rolling = None;
+    while pos + block <= target.len() {
+        let h = match rolling {
+            Some(h) => h,
+            None => hash(&target[pos..pos + block]),
+        };
+        let found = index
+            .get(&h)
+            .filter(|&&offset| base[offset..offset + block] == target[pos..pos + block]);
+        let Some(&offset) = found else {
+            rolling = (pos + block < target.len()).then(|| {
+                h.wrapping_sub((target[pos] as u32).wrapping_mul(top))
+                    .wrapping_mul(PRIME)
+                    .wrapping_add(target[pos + block] as u32)
+            });
+            pos += 1;
+            continue;
+        };
+
+        // Grow the match backwards over pending literals, then forwards.
+        let (mut start, mut from) = (pos, offset);
+        while start > literal && from > 0 && base[from - 1] == target[start - 1] {
+            start -= 1;
+            from -= 1;
+        }
+        let mut end = pos + block;
+        while end < target.len()
+            && from + (end - start) < base.len()
+            && base[from + (end - start)] == target[end]
+        {
+            end += 1;
+        }
+        add(&mut out, &target[literal..start]);
+        out.push(OP_COPY);
+        varint(&mut out, from as u64);
+        varint(&mut out, (end - start) as u64);
+        pos = end;
+        literal = end;
+        rolling = None;
+    }
+    add(&mut out, &target[literal..]);
+    out.push(OP_END);
+    out
+}
+
+/// Reads the header of a delta.
+pub fn read_header(delta: &[u8]) -> Result<DeltaHeader, DeltaError> {
+    header(&mut Reader::new(delta))
+}
+
+fn header(reader: &mut Reader) -> Result<DeltaHeader, DeltaError> {
+    if reader.take(DELTA_MAGIC.len()).ok() != Some(DELTA_MAGIC) {
+        return Err(DeltaError::NotADelta);
+    }
+    Ok(DeltaHeader {
+        base_size: reader.u64_le()?,
+        base_sha256: reader.hash()?,
+        target_size: reader.u64_le()?,
+        target_sha256: reader.hash()?,
+    })
+}
+
+/// Rebuilds the target from `base` and `delta`, checking both hashes.
+pub fn apply(base: &[u8], delta: &[u8]) -> Result<Vec<u8>, DeltaError> {
+    let mut reader = Reader::new(delta);
+    let header = header(&mut reader)?;
+    if base.len() as u64 != header.base_size || Sha256::digest(base)[..] != header.base_sha256 {
+        return Err(DeltaError::BaseMismatch {
+            size: header.base_size,
+            sha256: hex::encode(header.base_sha256),
+        });
+    }
+
+    // The header is untrusted: the buffer grows with the data, never past the recorded size.
+    let mut target = Vec::new();
+    loop {
+        let bytes = match reader.take(1)?[0] {
+            OP_END => break,
+            OP_COPY => {
+                let offset = reader.varint()?;
+                let len = reader.varint()?;
+                usize::try_from(offset)
+                    .ok()
+                    .zip(usize::try_from(len).ok())
+                    .and_then(|(offset, len)| base.get(offset..offset.checked_add(len)?))
+                    .ok_or(DeltaError::CopyOutsideBase)?
+            }
+            OP_ADD => {
+                let len = usize::try_from(reader.varint()?).map_err(|_| DeltaError::Truncated)?;
+                reader.take(len)?
+            }
+            tag => return Err(DeltaError::UnknownInstruction { tag }),
+        };
+        if (target.len() + bytes.len()) as u64 > header.target_size {
+            return Err(DeltaError::TargetTooLong {
+                size: header.target_size,
+            });
+        }
+        target.extend_from_slice(bytes);
+    }
+    if target.len() as u64 != header.target_size
+        || Sha256::digest(&target)[..] != header.target_sha256
+    {
+        return Err(DeltaError::TargetMismatch);
+    }
+    Ok(target)
+}
+
+/// `bigdiff apply`: writes the target rebuilt from `base` and `delta` to `output`, which must
+/// not exist yet.
+pub fn apply_file(base: &Path, delta: &Path, output: &Path) -> Result<()> {
+    let base_bytes = fs::read(base).map_err(Error::io(base))?;
+    let delta_bytes = fs::read(delta).map_err(Error::io(delta))?;
+    let target = apply(&base_bytes, &delta_bytes).map_err(|source| Error::Delta {
+        path: delta.to_path_buf(),
+        source,
+    })?;
+    if output.exists() {
+        return Err(Error::OutputExists {
+            path: output.to_path_buf(),
+        });
+    }
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(output)
+        .map_err(Error::io(output))?;
+    file.write_all(&target).map_err(Error::io(output))
+}
+
+/// `bigdiff apply` on folders: rebuilds every delta listed in the manifest of `diff_root`.
+///
+/// Each target is written under `dest_root` at its target-relative path, from the base file at
+/// the same place under `base_root`; no existing file is replaced. Returns the rebuilt paths.
+pub fn apply_tree(base_root: &Path, diff_root: &Path, dest_root: &Path) -> Result<Vec<PathBuf>> {
+    let manifest = Manifest::load(diff_root)?.ok_or_else(|| Error::Manifest {
+        path: diff_root.join(MANIFEST_FILE),
+        message: "not found; apply needs the manifest of a --binary-delta run".to_string(),
+    })?;
+    let mut rebuilt = Vec::new();
+    for entry in &manifest.outputs {
+        let (Some(base), Some(target)) = (&entry.base, &entry.target) else {
+            continue;
+        };
+        if !entry.path.to_string_lossy().ends_with(DELTA_SUFFIX) {
+            continue;
+        }
+        // A tampered manifest must not reach outside any of the three folders.
+        for path in [&entry.path, &base.path, &target.path] {
+            check_relative(path)?;
+        }
+        let output = dest_root.join(&target.path);
+        ensure_output_target_safe(dest_root, &output)?;
+        if let Some(parent) = output.parent() {
+            fs::create_dir_all(parent).map_err(Error::io(parent))?;
+        }
+        apply_file(
+            &base_root.join(&base.path),
+            &diff_root.join(&entry.path),
+            &output,
+        )?;
+        rebuilt.push(output);
+    }
+    Ok(rebuilt)
+}
+
+fn hash(window: &[u8]) -> u32 {
+    window
+        .iter()
+        .fold(0u32, |h, b| h.wrapping_mul(PRIME).wrapping_add(*b as u32))
+}
+
+fn add(out: &mut Vec<u8>, bytes: &[u8]) {
+    if bytes.is_empty() {
+        return;
+    }
+    out.push(OP_ADD);
+    varint(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+fn varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8 & 0x7F) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Reader { bytes }
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], DeltaError> {
+        if n > self.bytes.len() {
+            return Err(DeltaError::Truncated);
+        }
+        let (head, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        Ok(head)
+    }
+
+    fn u64_le(&mut self) -> Result<u64, DeltaError> {
+        let bytes = self.take(8)?;
+        Ok(u64::from_le_bytes(bytes.try_into().expect("8 bytes")))
+    }
+
+    fn hash(&mut self) -> Result<[u8; 32], DeltaError> {
+        Ok(self.take(32)?.try_into().expect("32 bytes"))
+    }
+
+    fn varint(&mut self) -> Result<u64, DeltaError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.take(1)?[0];
+            // The tenth byte holds bit 63 only; anything more does not fit in a u64.
+            if shift == 63 && byte > 1 {
+                return Err(DeltaError::MalformedNumber);
+            }
+            value |= ((byte & 0x7F) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(DeltaError::MalformedNumber)
+    }
+}
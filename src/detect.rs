@@ -0,0 +1,130 @@
+//
+// detect.rs
+// BigDiff-rs
+//
+// Sniffs language hints from file contents (shebang lines and Vim/Emacs modelines) so extensionless or mislabeled files get the right comment style.
+//
+// Thales Matheus Mendonça Santos - November 2025
+//
+// Content sniffing helpers that turn shebangs and editor modelines into language names.
+use std::path::Path;
+
+use crate::utils::read_head_and_tail;
+
+// Editors only honor modelines near the start or end of a file.
+const MODELINE_SCAN_LINES: usize = 5;
+const SNIFF_BYTES: u64 = 8192;
+
+/// Language hints found in a file's first and last lines.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ContentHints {
+    pub shebang: Option<String>,
+    pub modeline: Option<String>,
+}
+
+/// Reads the head and tail of a file and extracts shebang/modeline language names.
+pub fn sniff_file(path: &Path) -> ContentHints {
+    let Some((head, tail)) = read_head_and_tail(path, SNIFF_BYTES) else {
+        return ContentHints::default();
+    };
+    // The tail starts after the head, so on short files it may hold only a few lines: scan the
+    // last SNIFF_BYTES of both instead.
+    let tail = tail.map(|tail| {
+        let joined = [&head[..], &tail[..]].concat();
+        let start = joined.len().saturating_sub(SNIFF_BYTES as usize);
+        String::from_utf8_lossy(&joined[start..]).into_owned()
+    });
+    sniff_text(&String::from_utf8_lossy(&head), tail.as_deref())
+}
+
+/// Same as [`sniff_file`] but over already-loaded text (`tail` is `None` when `head` is the whole file).
+pub fn sniff_text(head: &str, tail: Option<&str>) -> ContentHints {
+    let head_lines: Vec<&str> = head.lines().take(MODELINE_SCAN_LINES).collect();
+    let tail_lines: Vec<&str> = {
+        let all: Vec<&str> = tail.unwrap_or(head).lines().collect();
+        all[all.len().saturating_sub(MODELINE_SCAN_LINES)..].to_vec()
+    };
+
+    let shebang = head_lines.first().and_then(|l| shebang_interpreter(l));
+
+    let modeline = head_lines
+        .iter()
+        .chain(tail_lines.iter())
+        .find_map(|l| vim_modeline(l).or_else(|| emacs_modeline(l)))
+        .or_else(|| emacs_local_variables(&tail_lines));
+
+    ContentHints { shebang, modeline }
+}
+
+/// Extracts the interpreter name from `#!/usr/bin/env node` or `#!/bin/bash -e`.
+pub fn shebang_interpreter(line: &str) -> Option<String> {
+    let rest = line.strip_prefix("#!")?;
+    let mut tokens = rest.split_whitespace();
+    let mut program = basename(tokens.next()?);
+
+    // `env` forwards to the real interpreter, possibly after flags or VAR=value pairs.
+    if program == "env" {
+        program = tokens
+            .find(|t| !t.starts_with('-') && !t.contains('='))
+            .map(basename)?;
+    }
+
+    // `python3.11` and `python3` behave like `python` for comment purposes.
+    let name = program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    (!name.is_empty()).then(|| name.to_lowercase())
+}
+
+fn basename(s: &str) -> &str {
+    s.rsplit('/').next().unwrap_or(s)
+}
+
+/// Parses Vim modelines such as `# vim: set ft=python:` or `// vi: filetype=javascript`.
+pub fn vim_modeline(line: &str) -> Option<String> {
+    let start = ["vim:", "vi:", "ex:", "Vim:"].iter().find_map(|marker| {
+        line.match_indices(marker)
+            .find(|(i, _)| *i == 0 || line[..*i].ends_with(char::is_whitespace))
+            .map(|(i, m)| i + m.len())
+    })?;
+
+    line[start..]
+        .split(|c: char| c.is_whitespace() || c == ':')
+        .filter_map(|opt| opt.split_once('='))
+        .find(|(key, _)| matches!(*key, "ft" | "filetype" | "syn" | "syntax"))
+        .map(|(_, value)| value.to_lowercase())
+        .filter(|v| !v.is_empty())
+}
+
+/// Parses Emacs file-variable lines such as `-*- mode: python -*-` or `-*- c++ -*-`.
+pub fn emacs_modeline(line: &str) -> Option<String> {
+    let start = line.find("-*-")? + 3;
+    let end = start + line[start..].find("-*-")?;
+    let vars = line[start..end].trim();
+
+    if !vars.contains(':') {
+        return normalize_mode(vars);
+    }
+    vars.split(';').find_map(|pair| {
+        let (key, value) = pair.split_once(':')?;
+        (key.trim().eq_ignore_ascii_case("mode"))
+            .then(|| normalize_mode(value))
+            .flatten()
+    })
+}
+
+// Emacs also reads a trailing `Local Variables:` block, e.g. `# mode: ruby`.
+fn emacs_local_variables(lines: &[&str]) -> Option<String> {
+    let start = lines.iter().position(|l| l.contains("Local Variables:"))?;
+    lines[start + 1..]
+        .iter()
+        .take_while(|l| !l.contains("End:"))
+        .find_map(|l| {
+            let (_, value) = l.split_once("mode:")?;
+            normalize_mode(value)
+        })
+}
+
+fn normalize_mode(value: &str) -> Option<String> {
+    let mode = value.trim().to_lowercase();
+    let mode = mode.strip_suffix("-mode").unwrap_or(&mode);
+    (!mode.is_empty() && !mode.contains(char::is_whitespace)).then(|| mode.to_string())
+}
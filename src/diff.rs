@@ -7,23 +7,38 @@
 // Thales Matheus Mendonça Santos - November 2025
 //
 // Core diff logic: detects changes between two trees and writes annotated outputs.
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsStr;
 use std::fs;
+use std::ops::{AddAssign, Bound};
 use std::path::{Path, PathBuf};
 
-use anyhow::Result;
+use serde::Serialize;
 use similar::{ChangeTag, TextDiff};
-use walkdir::WalkDir;
 
-use crate::cli::Options;
-use crate::comment::{comment_style_for, CommentStyle};
-use crate::scanner::{scan_dir, ScanResult};
-use crate::utils::{
-    avoid_collision, ensure_output_target_safe, file_bytes_equal, is_probably_binary,
-    read_text_best_effort, rel_parts_with_deleted_suffix,
+use crate::archive::{ArchiveKind, InputTree, ARCHIVE_MARKER};
+use crate::bindiff::analyze;
+use crate::classify::Classification;
+use crate::cli::{BinaryReport, OnExisting, Options, SidecarFormat};
+use crate::comment::{validate_annotation, CommentStyle};
+use crate::delta::encode;
+use crate::encoding::TextEncoding;
+use crate::error::{Error, Result};
+use crate::manifest::{Manifest, ManifestEntry, SourceFile, MANIFEST_FILE};
+use crate::naming::{
+    escape_archive_folder, escape_name, DELETED_MARKER, DELTA_SUFFIX, DIFF_SIDECAR, ERRORS_JSON,
+    ERRORS_TEXT, HEXDIFF_SIDECAR, HUNKS_SIDECAR, MODIFIED_MARKER, NEW_MARKER, NOTE, ROWS_REPORT,
+    SEMANTIC_REPORT,
 };
+use crate::scanner::{scan_dir, ScanResult};
+use crate::semantic::semantic_diff;
+use crate::sink::{open_sink, FsSink, NullSink, OutputFormat, OutputSink, PlannedOutput};
+use crate::staging::{prepare_output_dir, RecordingSink, ReplaySink, Staging};
+use crate::tabular::{delimiter_for, render_report, tabular_diff, RowChange};
+use crate::utils::{read_text, read_text_best_effort, sha256_bytes, sha256_file};
+use crate::xmlcanon::canonical_xml_diff;
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Serialize)]
 /// Counters used for the final summary printed to the user.
 pub struct Counters {
     pub same: usize,
@@ -31,7 +46,35 @@ pub struct Counters {
     pub del_files: usize,
     pub mod_text: usize,
     pub mod_binary: usize,
+    pub mod_archive: usize,
     pub del_dirs: usize,
+    /// Entries skipped because of an error (only with `--keep-going`).
+    pub errors: usize,
+}
+
+impl Counters {
+    /// Number of differences found (everything except equal files and errors).
+    pub fn changed(&self) -> usize {
+        self.new_files
+            + self.del_files
+            + self.mod_text
+            + self.mod_binary
+            + self.mod_archive
+            + self.del_dirs
+    }
+}
+
+impl AddAssign for Counters {
+    fn add_assign(&mut self, other: Self) {
+        self.same += other.same;
+        self.new_files += other.new_files;
+        self.del_files += other.del_files;
+        self.mod_text += other.mod_text;
+        self.mod_binary += other.mod_binary;
+        self.mod_archive += other.mod_archive;
+        self.del_dirs += other.del_dirs;
+        self.errors += other.errors;
+    }
 }
 
 /// Generates a unified text output where deleted lines are commented and new lines are marked.
@@ -43,8 +86,12 @@ pub fn annotate_text_diff(
 ) -> Result<String> {
     let a_text = read_text_best_effort(a_path, normalize_eol)?;
     let b_text = read_text_best_effort(b_path, normalize_eol)?;
+    Ok(annotate_text(&a_text, &b_text, style))
+}
 
-    let diff = TextDiff::from_lines(&a_text, &b_text);
+/// Same as [`annotate_text_diff`] but over already-loaded text.
+pub fn annotate_text(a_text: &str, b_text: &str, style: &CommentStyle) -> String {
+    let diff = TextDiff::from_lines(a_text, b_text);
     let mut output = String::new();
 
     for change in diff.iter_all_changes() {
@@ -54,206 +101,1243 @@ pub fn annotate_text_diff(
             ChangeTag::Insert => output.push_str(&style.append_new_suffix(change.value())),
         }
     }
-    Ok(output)
+    output
 }
 
-/// Copies a deleted directory subtree to the output folder, suffixing names with `.deleted`.
-fn copy_deleted_tree(
-    head_rel: &Path,
-    scan_a: &ScanResult,
-    out_root: &Path,
-    counters: &mut Counters,
-) -> Result<HashSet<PathBuf>> {
-    let mut processed = HashSet::new();
-    let head_abs = scan_a.root.join(head_rel);
-
-    for entry in WalkDir::new(&head_abs).into_iter().filter_map(|e| e.ok()) {
-        let path = entry.path();
-        let rel_from_root = match path.strip_prefix(&scan_a.root) {
-            Ok(r) => r,
-            Err(_) => continue,
-        };
+#[derive(Debug, Clone, Serialize)]
+/// A group of nearby changes with context, as in a unified diff (line numbers are 1-based).
+pub struct Hunk {
+    pub old_start: usize,
+    pub old_lines: usize,
+    pub new_start: usize,
+    pub new_lines: usize,
+    pub lines: Vec<HunkLine>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HunkLine {
+    /// `" "` for context, `"-"` for deleted and `"+"` for added lines.
+    pub tag: &'static str,
+    pub text: String,
+}
 
-        if !rel_from_root.starts_with(head_rel) {
+/// Splits a line diff into hunks with three lines of context.
+pub fn compute_hunks(a_text: &str, b_text: &str) -> Vec<Hunk> {
+    let diff = TextDiff::from_lines(a_text, b_text);
+    let mut hunks = Vec::new();
+
+    for group in diff.grouped_ops(3) {
+        let (Some(first), Some(last)) = (group.first(), group.last()) else {
             continue;
+        };
+        let old = first.old_range().start..last.old_range().end;
+        let new = first.new_range().start..last.new_range().end;
+
+        let mut lines = Vec::new();
+        for op in &group {
+            for change in diff.iter_changes(op) {
+                let tag = match change.tag() {
+                    ChangeTag::Equal => " ",
+                    ChangeTag::Delete => "-",
+                    ChangeTag::Insert => "+",
+                };
+                let text = change.value();
+                let text = text.strip_suffix('\n').unwrap_or(text);
+                lines.push(HunkLine {
+                    tag,
+                    text: text.to_string(),
+                });
+            }
         }
 
-        let dest_path = out_root.join(rel_parts_with_deleted_suffix(rel_from_root));
+        hunks.push(Hunk {
+            old_start: old.start + 1,
+            old_lines: old.len(),
+            new_start: new.start + 1,
+            new_lines: new.len(),
+            lines,
+        });
+    }
+    hunks
+}
 
-        if entry.file_type().is_dir() {
-            ensure_output_target_safe(out_root, &dest_path)?;
-            fs::create_dir_all(&dest_path)?;
-            if rel_from_root == head_rel {
-                counters.del_dirs += 1;
-            }
-        } else {
-            let mut dest_file = dest_path;
-            if let Some(name) = dest_file.file_name() {
-                let mut new_name = name.to_os_string();
-                new_name.push(".deleted");
-                dest_file.set_file_name(new_name);
-            }
+/// Renders the companion file used by the sidecar strategy and returns it with its suffix.
+pub fn render_sidecar(
+    rel: &Path,
+    a_text: &str,
+    b_text: &str,
+    format: SidecarFormat,
+) -> Result<(&'static str, String)> {
+    match format {
+        SidecarFormat::Diff => {
+            let rel = rel.to_string_lossy().replace('\\', "/");
+            let diff = TextDiff::from_lines(a_text, b_text)
+                .unified_diff()
+                .context_radius(3)
+                .header(&format!("a/{rel}"), &format!("b/{rel}"))
+                .to_string();
+            Ok((DIFF_SIDECAR, diff))
+        }
+        SidecarFormat::Json => {
+            let hunks = compute_hunks(a_text, b_text);
+            let json = serde_json::to_string_pretty(&hunks).map_err(|e| Error::Render {
+                message: e.to_string(),
+            })?;
+            Ok((HUNKS_SIDECAR, json + "\n"))
+        }
+    }
+}
 
-            if let Some(parent) = dest_file.parent() {
-                ensure_output_target_safe(out_root, parent)?;
-                fs::create_dir_all(parent)?;
-            }
+#[derive(Debug, Clone)]
+/// One classification produced while walking two trees.
+///
+/// `rel` is relative to the compared roots; members of nested archives appear under
+/// `<archive>!/`. Source paths may point into temporary extraction folders and are only
+/// valid until the visitor returns.
+pub enum DiffEvent {
+    /// Identical in both trees (or equivalent under a structural comparison).
+    Equal { rel: PathBuf },
+    /// Only present in the target tree.
+    Added { rel: PathBuf, target: PathBuf },
+    /// Only present in the base tree.
+    Deleted { rel: PathBuf, base: PathBuf },
+    /// A folder only present in the base tree; reported before the files it contains.
+    DirDeleted { rel: PathBuf, base: PathBuf },
+    /// A text file that differs.
+    Modified {
+        rel: PathBuf,
+        base: PathBuf,
+        target: PathBuf,
+        base_text: String,
+        target_text: String,
+        /// How each side was stored; the texts above are already decoded.
+        base_encoding: TextEncoding,
+        target_encoding: TextEncoding,
+        /// Semantic key paths or XML locations; empty for a plain line diff.
+        structural: Vec<String>,
+        /// Row-level changes when a keyed CSV/TSV comparison applies.
+        rows: Option<Vec<RowChange>>,
+        /// Hex SHA-256 of both files, computed when they were compared.
+        base_sha256: String,
+        target_sha256: String,
+    },
+    /// A file that differs but is not diffed line by line: binary on either side, or text
+    /// over `--max-text-size` on either side.
+    BinaryModified {
+        rel: PathBuf,
+        base: PathBuf,
+        target: PathBuf,
+        base_size: u64,
+        target_size: u64,
+        base_class: Classification,
+        target_class: Classification,
+        /// Hex SHA-256 of both files, computed when they were compared.
+        base_sha256: String,
+        target_sha256: String,
+    },
+    /// A nested archive whose members differ; reported after the member events.
+    ArchiveModified { rel: PathBuf },
+    /// A problem that was worked around, e.g. an unreadable nested archive compared as binary.
+    Warning { rel: PathBuf, message: String },
+    /// An entry that could not be processed; only reported with `--keep-going`, otherwise the
+    /// walk stops with the error.
+    Error { rel: PathBuf, message: String },
+}
+
+impl DiffEvent {
+    /// Path of the entry the event is about.
+    pub fn rel(&self) -> &Path {
+        match self {
+            DiffEvent::Equal { rel }
+            | DiffEvent::Added { rel, .. }
+            | DiffEvent::Deleted { rel, .. }
+            | DiffEvent::DirDeleted { rel, .. }
+            | DiffEvent::Modified { rel, .. }
+            | DiffEvent::BinaryModified { rel, .. }
+            | DiffEvent::ArchiveModified { rel }
+            | DiffEvent::Warning { rel, .. }
+            | DiffEvent::Error { rel, .. } => rel,
+        }
+    }
+
+    /// Short name of the event kind (`added`, `modified`, ...), as used in dry-run plans.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            DiffEvent::Equal { .. } => "equal",
+            DiffEvent::Added { .. } => "added",
+            DiffEvent::Deleted { .. } => "deleted",
+            DiffEvent::DirDeleted { .. } => "dir-deleted",
+            DiffEvent::Modified { .. } => "modified",
+            DiffEvent::BinaryModified { .. } => "binary-modified",
+            DiffEvent::ArchiveModified { .. } => "archive-modified",
+            DiffEvent::Warning { .. } => "warning",
+            DiffEvent::Error { .. } => "error",
+        }
+    }
 
-            // Avoid overwriting existing outputs when two files map to the same destination.
-            let dest_file = avoid_collision(&dest_file);
-            ensure_output_target_safe(out_root, &dest_file)?;
-            fs::copy(path, &dest_file)?;
-            counters.del_files += 1;
-            processed.insert(rel_from_root.to_path_buf());
+    /// Line hunks of a [`DiffEvent::Modified`] text, with three lines of context.
+    ///
+    /// Computed on demand, so consumers that render the texts their own way diff them only once.
+    pub fn hunks(&self) -> Option<Vec<Hunk>> {
+        match self {
+            DiffEvent::Modified {
+                base_text,
+                target_text,
+                ..
+            } => Some(compute_hunks(base_text, target_text)),
+            _ => None,
         }
     }
-    Ok(processed)
+
+    /// Whether the event reports a difference between the trees.
+    pub fn is_change(&self) -> bool {
+        !matches!(
+            self,
+            DiffEvent::Equal { .. } | DiffEvent::Warning { .. } | DiffEvent::Error { .. }
+        )
+    }
 }
 
 /// Main orchestration: walks both trees, classifies changes, and writes annotated copies.
+///
+/// `out_root` is a directory, or the archive file to create when `opts.output_format` asks for one.
+/// Directory outputs are staged and moved into place on success (see [`crate::staging`]);
+/// archives are written to `<name>.partial` and renamed when complete.
 pub fn run_bigdiff(
     a_root: &Path,
     b_root: &Path,
     out_root: &Path,
     opts: &Options,
 ) -> Result<Counters> {
-    // Snapshot both directory trees first to make the subsequent logic deterministic.
-    let scan_a = scan_dir(a_root, &opts.ignore_patterns);
-    let scan_b = scan_dir(b_root, &opts.ignore_patterns);
+    opts.check_output_format()?;
+    if opts.output_format != OutputFormat::Dir {
+        if opts.on_existing == OnExisting::Error && out_root.exists() {
+            return Err(Error::OutputExists {
+                path: out_root.to_path_buf(),
+            });
+        }
+        let mut sink = open_sink(opts.output_format, out_root)?;
+        let mut writer = OutputWriter {
+            opts,
+            counters: Counters::default(),
+            deleted_heads: Vec::new(),
+            errors: Vec::new(),
+        };
+        let mut outputs = Vec::new();
+        visit_diff(a_root, b_root, opts, |event| {
+            let origin = OutputOrigin::of(&event);
+            let errors_before = writer.errors.len();
+            let mut recording = RecordingSink::new(sink.as_mut(), origin.hashes.clone());
+            writer.handle(&mut recording, event)?;
+            // Sources of a failed entry may be unreadable; list its partial outputs untagged.
+            if writer.errors.len() == errors_before {
+                outputs.extend(origin.tag(recording)?);
+            } else {
+                outputs.extend(recording.written);
+            }
+            Ok(())
+        })?;
+        let mut recording = RecordingSink::new(sink.as_mut(), HashMap::new());
+        writer.write_error_manifest(&mut recording)?;
+        outputs.extend(OutputOrigin::errors().tag(recording)?);
+        let manifest = Manifest::new(opts, outputs).to_json()?;
+        sink.write_file(Path::new(MANIFEST_FILE), manifest.as_bytes())?;
+        sink.finish()?;
+        return Ok(writer.counters);
+    }
 
-    let mut counters = Counters::default();
+    // Without a journal there is nothing to resume: the folder gets the usual policy.
+    let resuming = opts.resume && Staging::can_resume(out_root);
+    if !resuming {
+        prepare_output_dir(out_root, opts.on_existing)?;
+    }
+    let mut staging = Staging::open(out_root, resuming)?;
+    let mut sink = FsSink::new(staging.dir());
+    let mut writer = OutputWriter {
+        opts,
+        counters: Counters::default(),
+        deleted_heads: Vec::new(),
+        errors: Vec::new(),
+    };
 
-    // Identify directories present only in A, keeping just the highest-level heads.
-    let del_dirs_all: Vec<_> = scan_a
-        .dirs
-        .iter()
-        .filter(|d| !scan_b.dirs.contains(*d))
-        .collect();
+    visit_diff(a_root, b_root, opts, |event| {
+        let rel = event.rel().to_path_buf();
+        if staging.is_done(&rel) {
+            // Finished before the interruption: count it, write nothing.
+            return writer.handle(&mut ReplaySink, event);
+        }
+        let origin = OutputOrigin::of(&event);
+        let errors_before = writer.errors.len();
+        let mut recording = RecordingSink::new(&mut sink, origin.hashes.clone());
+        writer.handle(&mut recording, event)?;
+        if !recording.written.is_empty() && writer.errors.len() == errors_before {
+            staging.record(&rel, origin.tag(recording)?)?;
+        }
+        Ok(())
+    })?;
 
-    let mut head_del_dirs: Vec<&PathBuf> = Vec::new();
-    let mut sorted_dirs = del_dirs_all.clone();
-    sorted_dirs.sort_by_key(|p| p.components().count());
+    let mut recording = RecordingSink::new(&mut sink, HashMap::new());
+    writer.write_error_manifest(&mut recording)?;
+    staging.describe(OutputOrigin::errors().tag(recording)?);
+    staging.finish(opts)?;
+    Ok(writer.counters)
+}
 
-    for d in sorted_dirs {
-        if !head_del_dirs
-            .iter()
-            .any(|head| d.starts_with(head) && d != *head)
-        {
-            head_del_dirs.push(d);
+// What the manifest records about the event behind a set of outputs.
+struct OutputOrigin {
+    category: &'static str,
+    rel: PathBuf,
+    base: Option<PathBuf>,
+    target: Option<PathBuf>,
+    // Digests the comparison already computed, by source path.
+    hashes: HashMap<PathBuf, String>,
+}
+
+impl OutputOrigin {
+    fn of(event: &DiffEvent) -> Self {
+        let (base, target, hashes) = match event {
+            DiffEvent::Added { target, .. } => (None, Some(target), None),
+            DiffEvent::Deleted { base, .. } => (Some(base), None, None),
+            DiffEvent::Modified {
+                base,
+                target,
+                base_sha256,
+                target_sha256,
+                ..
+            }
+            | DiffEvent::BinaryModified {
+                base,
+                target,
+                base_sha256,
+                target_sha256,
+                ..
+            } => (
+                Some(base),
+                Some(target),
+                Some([(base, base_sha256), (target, target_sha256)]),
+            ),
+            _ => (None, None, None),
+        };
+        OutputOrigin {
+            category: event.kind(),
+            rel: event.rel().to_path_buf(),
+            base: base.cloned(),
+            target: target.cloned(),
+            hashes: hashes
+                .into_iter()
+                .flatten()
+                .map(|(path, sha256)| (path.clone(), sha256.clone()))
+                .collect(),
         }
     }
 
-    // Copy deleted directory trees first so nested files are already accounted for.
-    let mut processed_deleted_files = HashSet::new();
-    for head in head_del_dirs {
-        let processed = copy_deleted_tree(head, &scan_a, out_root, &mut counters)?;
-        processed_deleted_files.extend(processed);
+    fn errors() -> Self {
+        OutputOrigin {
+            category: "errors",
+            rel: PathBuf::new(),
+            base: None,
+            target: None,
+            hashes: HashMap::new(),
+        }
     }
 
-    // Copy deleted standalone files that were not covered by a deleted directory.
-    for (rel_a, abs_a) in &scan_a.files {
-        if processed_deleted_files.contains(rel_a) {
-            continue;
+    // Sources are described only when the event produced outputs; a source copied to the
+    // outputs or compared with the other side is not hashed again.
+    fn tag(&self, recording: RecordingSink) -> Result<Vec<ManifestEntry>> {
+        let RecordingSink {
+            written, hashes, ..
+        } = recording;
+        if written.is_empty() {
+            return Ok(written);
         }
-        if !scan_b.files.contains_key(rel_a) {
-            let mut dst = out_root.join(rel_a);
-            if let Some(name) = dst.file_name() {
-                let mut new_name = name.to_os_string();
-                new_name.push(".deleted");
-                dst.set_file_name(new_name);
+        let source = |path: &Option<PathBuf>| {
+            path.as_deref()
+                .map(|path| match hashes.get(path) {
+                    Some(sha256) => SourceFile::with_sha256(&self.rel, path, sha256.clone()),
+                    None => SourceFile::read(&self.rel, path),
+                })
+                .transpose()
+        };
+        let (base, target) = (source(&self.base)?, source(&self.target)?);
+        Ok(written
+            .into_iter()
+            .map(|output| ManifestEntry {
+                category: Some(self.category.to_string()),
+                base: base.clone(),
+                target: target.clone(),
+                ..output
+            })
+            .collect())
+    }
+}
+
+/// Compares two trees and sends every output through `sink`.
+///
+/// The caller owns the sink and is responsible for calling [`OutputSink::finish`].
+pub fn run_bigdiff_with_sink(
+    a_root: &Path,
+    b_root: &Path,
+    sink: &mut dyn OutputSink,
+    opts: &Options,
+) -> Result<Counters> {
+    let mut writer = OutputWriter {
+        opts,
+        counters: Counters::default(),
+        deleted_heads: Vec::new(),
+        errors: Vec::new(),
+    };
+    visit_diff(a_root, b_root, opts, |event| writer.handle(sink, event))?;
+    writer.write_error_manifest(sink)?;
+    Ok(writer.counters)
+}
+
+#[derive(Debug, Serialize)]
+/// What a run would do, without writing anything.
+pub struct Plan {
+    pub counters: Counters,
+    /// One entry per classified path, except equal files, which produce no output.
+    pub actions: Vec<PlannedAction>,
+}
+
+#[derive(Debug, Serialize)]
+/// The outputs one classification would produce.
+pub struct PlannedAction {
+    /// Event kind, see [`DiffEvent::kind`]; `errors-manifest` for the ERRORS files.
+    pub action: &'static str,
+    pub rel: PathBuf,
+    /// Destinations, exactly as a real run would name them.
+    pub outputs: Vec<PlannedOutput>,
+    /// Reason for `warning` and `error` actions.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+/// Runs the full classification (hashing, binary detection, size limits) through a
+/// [`NullSink`] and returns every planned action. Nothing is written.
+pub fn plan_bigdiff(a_root: &Path, b_root: &Path, opts: &Options) -> Result<Plan> {
+    let mut sink = NullSink::new();
+    let mut writer = OutputWriter {
+        opts,
+        counters: Counters::default(),
+        deleted_heads: Vec::new(),
+        errors: Vec::new(),
+    };
+    let mut actions = Vec::new();
+
+    visit_diff(a_root, b_root, opts, |event| {
+        if matches!(event, DiffEvent::Equal { .. }) {
+            return writer.handle(&mut sink, event);
+        }
+        let action = event.kind();
+        let rel = event.rel().to_path_buf();
+        let mut message = match &event {
+            DiffEvent::Warning { message, .. } | DiffEvent::Error { message, .. } => {
+                Some(message.clone())
             }
-            if let Some(p) = dst.parent() {
-                ensure_output_target_safe(out_root, p)?;
-                fs::create_dir_all(p)?;
+            _ => None,
+        };
+        let errors_before = writer.errors.len();
+        writer.handle(&mut sink, event)?;
+        // With `keep_going`, a failed write is recorded by the writer instead of returned.
+        let action = match writer.errors.get(errors_before) {
+            Some(record) if action != "error" => {
+                message = Some(record.error.clone());
+                "error"
             }
-            dst = avoid_collision(&dst);
-            ensure_output_target_safe(out_root, &dst)?;
-            fs::copy(abs_a, dst)?;
-            counters.del_files += 1;
+            _ => action,
+        };
+        actions.push(PlannedAction {
+            action,
+            rel,
+            outputs: sink.take_log(),
+            message,
+        });
+        Ok(())
+    })?;
+
+    writer.write_error_manifest(&mut sink)?;
+    let outputs = sink.take_log();
+    if !outputs.is_empty() {
+        actions.push(PlannedAction {
+            action: "errors-manifest",
+            rel: PathBuf::new(),
+            outputs,
+            message: None,
+        });
+    }
+    Ok(Plan {
+        counters: writer.counters,
+        actions,
+    })
+}
+
+/// Walks both trees and reports every classification to `visitor` without writing anything.
+///
+/// Events arrive in this order: deleted folders with their contents, deleted files, new files,
+/// then common files. With `opts.keep_going`, a file that cannot be compared or a folder that
+/// cannot be read is reported as [`DiffEvent::Error`] and the walk continues. An error returned by the visitor always stops it.
+pub fn visit_diff(
+    a_root: &Path,
+    b_root: &Path,
+    opts: &Options,
+    mut visitor: impl FnMut(DiffEvent) -> Result<()>,
+) -> Result<()> {
+    let mut walk = Walk {
+        prefix: PathBuf::new(),
+        opts,
+        visitor: &mut visitor,
+        changes: 0,
+        visitor_failed: false,
+        depth: 0,
+    };
+    walk.trees(a_root, b_root)
+}
+
+/// State for one (possibly nested) tree comparison.
+struct Walk<'a> {
+    /// Prepended to every reported path; `<archive>!` inside nested archives.
+    prefix: PathBuf,
+    opts: &'a Options,
+    visitor: &'a mut dyn FnMut(DiffEvent) -> Result<()>,
+    /// Number of change events reported so far.
+    changes: usize,
+    /// Set once the visitor returns an error, which must never be swallowed by `keep_going`.
+    visitor_failed: bool,
+    /// Number of archives this walk is nested in.
+    depth: usize,
+}
+
+impl Walk<'_> {
+    fn emit(&mut self, event: DiffEvent) -> Result<()> {
+        if event.is_change() {
+            self.changes += 1;
+        }
+        let result = (self.visitor)(event);
+        self.visitor_failed |= result.is_err();
+        result
+    }
+
+    /// Reported path of `rel`; real names that read as `<archive>!` folders get escaped.
+    fn rel(&self, rel: &Path) -> PathBuf {
+        if !self.opts.recurse_archives {
+            return self.prefix.join(rel);
+        }
+        let mut full = self.prefix.clone();
+        for part in rel.components() {
+            full.push(escape_archive_folder(part.as_os_str()));
         }
+        full
     }
 
-    // Copy brand new files from B, tagging them with `.new`.
-    for (rel_b, abs_b) in &scan_b.files {
-        if !scan_a.files.contains_key(rel_b) {
-            let mut dst = out_root.join(rel_b);
-            if let Some(name) = dst.file_name() {
-                let mut new_name = name.to_os_string();
-                new_name.push(".new");
-                dst.set_file_name(new_name);
+    fn trees(&mut self, a_root: &Path, b_root: &Path) -> Result<()> {
+        let opts = self.opts;
+
+        // Snapshot both directory trees first to make the subsequent logic deterministic.
+        let mut scan_a = scan_dir(a_root, &opts.ignore_patterns);
+        let mut scan_b = scan_dir(b_root, &opts.ignore_patterns);
+        let unreadable = std::mem::take(&mut scan_a.errors)
+            .into_iter()
+            .chain(std::mem::take(&mut scan_b.errors));
+        for (rel, err) in unreadable {
+            self.recover(&rel, err)?;
+        }
+
+        // Identify directories present only in A, keeping just the highest-level heads.
+        let del_dirs_all: Vec<_> = scan_a
+            .dirs
+            .iter()
+            .filter(|d| !scan_b.dirs.contains(*d))
+            .collect();
+
+        let mut head_del_dirs: Vec<&PathBuf> = Vec::new();
+        let mut sorted_dirs = del_dirs_all.clone();
+        sorted_dirs.sort_by_key(|p| p.components().count());
+
+        for d in sorted_dirs {
+            if !head_del_dirs
+                .iter()
+                .any(|head| d.starts_with(head) && d != *head)
+            {
+                head_del_dirs.push(d);
             }
-            if let Some(p) = dst.parent() {
-                ensure_output_target_safe(out_root, p)?;
-                fs::create_dir_all(p)?;
+        }
+
+        // Report deleted directory trees first so nested files are already accounted for.
+        let mut processed_deleted_files = HashSet::new();
+        for head in head_del_dirs {
+            let processed = self.deleted_tree(head, &scan_a)?;
+            processed_deleted_files.extend(processed);
+        }
+
+        // Deleted standalone files that were not covered by a deleted directory.
+        for (rel_a, abs_a) in &scan_a.files {
+            if processed_deleted_files.contains(rel_a) {
+                continue;
+            }
+            if !scan_b.files.contains_key(rel_a) {
+                self.emit(DiffEvent::Deleted {
+                    rel: self.rel(rel_a),
+                    base: abs_a.clone(),
+                })?;
             }
-            dst = avoid_collision(&dst);
-            ensure_output_target_safe(out_root, &dst)?;
-            fs::copy(abs_b, dst)?;
-            counters.new_files += 1;
         }
+
+        // Brand new files from B.
+        for (rel_b, abs_b) in &scan_b.files {
+            if !scan_a.files.contains_key(rel_b) {
+                self.emit(DiffEvent::Added {
+                    rel: self.rel(rel_b),
+                    target: abs_b.clone(),
+                })?;
+            }
+        }
+
+        let common_files: Vec<_> = scan_a
+            .files
+            .keys()
+            .filter(|k| scan_b.files.contains_key(*k))
+            .collect();
+
+        for rel in common_files {
+            if let Err(err) = self.common_file(rel, &scan_a.files[rel], &scan_b.files[rel]) {
+                self.recover(rel, err)?;
+            }
+        }
+        Ok(())
     }
 
-    let common_files: Vec<_> = scan_a
-        .files
-        .keys()
-        .filter(|k| scan_b.files.contains_key(*k))
-        .collect();
+    /// With `keep_going`, reports `err` for `rel` as a [`DiffEvent::Error`]; otherwise returns it.
+    fn recover(&mut self, rel: &Path, err: Error) -> Result<()> {
+        if !self.opts.keep_going || self.visitor_failed {
+            return Err(err);
+        }
+        self.emit(DiffEvent::Error {
+            rel: self.rel(rel),
+            message: err.to_string(),
+        })
+    }
 
-    // For files that exist in both trees, decide whether to annotate a text diff or copy as binary.
-    for rel in common_files {
-        let a_file = &scan_a.files[rel];
-        let b_file = &scan_b.files[rel];
+    /// Reports a deleted directory subtree and returns the files it contained.
+    fn deleted_tree(&mut self, head_rel: &Path, scan_a: &ScanResult) -> Result<HashSet<PathBuf>> {
+        let mut processed = HashSet::new();
+        // Everything under `head_rel` sorts right after it.
+        let from = (Bound::Included(head_rel), Bound::Unbounded);
+        let dirs = scan_a
+            .dirs
+            .range::<Path, _>(from)
+            .take_while(|rel| rel.starts_with(head_rel))
+            .map(|rel| (rel, true));
+        let files = scan_a
+            .files
+            .range::<Path, _>(from)
+            .map(|(rel, _)| rel)
+            .take_while(|rel| rel.starts_with(head_rel))
+            .map(|rel| (rel, false));
+        // Merged in walk order: each folder before its contents, names sorted.
+        let mut entries: Vec<_> = dirs.chain(files).collect();
+        entries.sort();
 
-        if file_bytes_equal(a_file, b_file) {
-            counters.same += 1;
-            continue;
+        for (rel_from_root, is_dir) in entries {
+            let rel = self.rel(rel_from_root);
+            let base = scan_a.root.join(rel_from_root);
+            if is_dir {
+                self.emit(DiffEvent::DirDeleted { rel, base })?;
+            } else {
+                self.emit(DiffEvent::Deleted { rel, base })?;
+                processed.insert(rel_from_root.clone());
+            }
+        }
+        Ok(processed)
+    }
+
+    /// Decides whether a file present in both trees is equal, text-modified or binary-modified.
+    fn common_file(&mut self, rel: &Path, a_file: &Path, b_file: &Path) -> Result<()> {
+        let opts = self.opts;
+        let full_rel = self.rel(rel);
+
+        // Hashed once here; the outputs and the manifest reuse the digests.
+        let (a_sha256, b_sha256) = (sha256_file(a_file)?, sha256_file(b_file)?);
+        if a_sha256 == b_sha256 {
+            return self.emit(DiffEvent::Equal { rel: full_rel });
         }
 
-        // Choose comment syntax based on file extension and prepare output path.
-        let style = comment_style_for(rel);
-        let mut dst = out_root.join(rel);
-        if let Some(name) = dst.file_name() {
-            let mut new_name = name.to_os_string();
-            new_name.push(".modified");
-            dst.set_file_name(new_name);
+        // Nested archives are expanded and diffed member by member under `<name>!/`.
+        if opts.recurse_archives && self.nested_archives(rel, a_file, b_file)? {
+            return Ok(());
         }
-        if let Some(p) = dst.parent() {
-            ensure_output_target_safe(out_root, p)?;
-            fs::create_dir_all(p)?;
+
+        // Both sides must be text, and neither may be too large to load.
+        let size_a = fs::metadata(a_file).map_err(Error::io(a_file))?.len();
+        let size_b = fs::metadata(b_file).map_err(Error::io(b_file))?.len();
+        let forced = opts.encodings.for_path(rel);
+        let class_a = opts.classifier.classify(a_file, rel, forced);
+        let class_b = opts.classifier.classify(b_file, rel, forced);
+        if !(class_a.is_text && class_b.is_text) || size_a.max(size_b) > opts.max_text_size {
+            return self.emit(DiffEvent::BinaryModified {
+                rel: full_rel,
+                base: a_file.to_path_buf(),
+                target: b_file.to_path_buf(),
+                base_size: size_a,
+                target_size: size_b,
+                base_class: class_a,
+                target_class: class_b,
+                base_sha256: a_sha256,
+                target_sha256: b_sha256,
+            });
         }
-        dst = avoid_collision(&dst);
-        ensure_output_target_safe(out_root, &dst)?;
 
-        let size_b = fs::metadata(b_file)?.len();
-        let is_bin = is_probably_binary(b_file);
+        let (a_text, a_encoding) = read_text(a_file, forced, opts.normalize_eol)?;
+        let (b_text, b_encoding) = read_text(b_file, forced, opts.normalize_eol)?;
 
-        if is_bin || size_b > opts.max_text_size {
-            fs::copy(b_file, &dst)?;
-            counters.mod_binary += 1;
+        // Structured documents compare by content; reformatting or reordering is not a change.
+        let structural = match structural_changes(rel, &a_text, &b_text, opts) {
+            Ok(structural) => structural,
+            Err(err) => {
+                // Falls back to a line diff.
+                self.emit(DiffEvent::Warning {
+                    rel: full_rel.clone(),
+                    message: format!("{err}; compared line by line"),
+                })?;
+                None
+            }
+        };
+        if structural
+            .as_ref()
+            .is_some_and(|changes| changes.is_empty())
+        {
+            return self.emit(DiffEvent::Equal { rel: full_rel });
+        }
 
-            let mut note_path = dst.clone();
-            if let Some(name) = note_path.file_name() {
-                let mut new_name = name.to_os_string();
-                new_name.push(".NOTE.txt");
-                note_path.set_file_name(new_name);
+        // Keyed CSV/TSV comparison: row and column order do not matter.
+        let rows = match delimiter_for(rel) {
+            Some(delimiter) if !opts.csv_keys.is_empty() => {
+                match tabular_diff(&a_text, &b_text, delimiter, &opts.csv_keys) {
+                    Ok(changes) => Some(changes),
+                    Err(err) => {
+                        // Falls back to a line diff.
+                        self.emit(DiffEvent::Warning {
+                            rel: full_rel.clone(),
+                            message: err.to_string(),
+                        })?;
+                        None
+                    }
+                }
             }
-            // Leave a small hint for the user explaining why we skipped a line diff.
-            let note_content = format!(
-                "File treated as binary or too large for line diff.\n\
-Base origin (A): {:?}\n\
-Target origin (B): {:?}\n\
-Size: {} bytes\n\
-Strategy: direct copy from target to '.modified'.\n",
-                a_file, b_file, size_b
-            );
-            ensure_output_target_safe(out_root, &note_path)?;
-            fs::write(note_path, note_content)?;
+            _ => None,
+        };
+        if rows.as_ref().is_some_and(|changes| changes.is_empty()) {
+            return self.emit(DiffEvent::Equal { rel: full_rel });
+        }
+
+        self.emit(DiffEvent::Modified {
+            rel: full_rel,
+            base: a_file.to_path_buf(),
+            target: b_file.to_path_buf(),
+            base_text: a_text,
+            target_text: b_text,
+            base_encoding: a_encoding,
+            target_encoding: b_encoding,
+            structural: structural.unwrap_or_default(),
+            rows,
+            base_sha256: a_sha256,
+            target_sha256: b_sha256,
+        })
+    }
+
+    /// Expands two versions of an archive and reports their members under `<rel>!/`.
+    ///
+    /// Returns `false` when `rel` is not an archive, either side fails to extract or goes over
+    /// the archive limits, or the archive sits too deep, so the caller falls back to the binary
+    /// handling.
+    fn nested_archives(&mut self, rel: &Path, a_file: &Path, b_file: &Path) -> Result<bool> {
+        let Some(kind) = ArchiveKind::for_path(rel) else {
+            return Ok(false);
+        };
+        let full_rel = self.rel(rel);
+        let limits = &self.opts.archive_limits;
+        if self.depth >= limits.max_depth {
+            self.emit(DiffEvent::Warning {
+                rel: full_rel,
+                message: format!(
+                    "archive is nested deeper than the limit of {}; compared as a binary file",
+                    limits.max_depth
+                ),
+            })?;
+            return Ok(false);
+        }
+        let (a_tree, b_tree) = match (
+            InputTree::extract(a_file, kind, limits),
+            InputTree::extract(b_file, kind, limits),
+        ) {
+            (Ok(a_tree), Ok(b_tree)) => (a_tree, b_tree),
+            (Err(err), _) | (_, Err(err)) => {
+                // Name the archive as reported, not the temporary copy it may have been read from.
+                let err = match err {
+                    Error::Archive { source, .. } => Error::Archive {
+                        path: full_rel.clone(),
+                        source,
+                    },
+                    err => err,
+                };
+                self.emit(DiffEvent::Warning {
+                    rel: full_rel,
+                    message: err.to_string(),
+                })?;
+                return Ok(false);
+            }
+        };
+
+        let mut nested = Walk {
+            prefix: with_suffix(&full_rel, ARCHIVE_MARKER),
+            opts: self.opts,
+            visitor: &mut *self.visitor,
+            changes: 0,
+            visitor_failed: false,
+            depth: self.depth + 1,
+        };
+        let walked = nested.trees(&a_tree.root, &b_tree.root);
+        let changed = nested.changes > 0;
+        self.changes += nested.changes;
+        self.visitor_failed |= nested.visitor_failed;
+        walked?;
+
+        if changed {
+            self.emit(DiffEvent::ArchiveModified { rel: full_rel })?;
+        } else {
+            self.emit(DiffEvent::Equal { rel: full_rel })?;
+        }
+        Ok(true)
+    }
+}
+
+/// Turns diff events into output files and tallies the summary counters.
+struct OutputWriter<'a> {
+    opts: &'a Options,
+    counters: Counters,
+    /// Top-level deleted folders seen so far; their contents get `.deleted` names throughout.
+    deleted_heads: Vec<PathBuf>,
+    /// Entries that could not be processed, for the ERRORS manifest.
+    errors: Vec<ErrorRecord>,
+}
+
+#[derive(Serialize)]
+/// One line of the ERRORS manifest.
+struct ErrorRecord {
+    path: String,
+    error: String,
+}
+
+impl OutputWriter<'_> {
+    /// Writes the outputs for one event; with `keep_going`, I/O failures are recorded instead.
+    fn handle(&mut self, sink: &mut dyn OutputSink, event: DiffEvent) -> Result<()> {
+        let rel = event.rel().to_path_buf();
+        match self.write(sink, event) {
+            Err(err) if self.opts.keep_going && err.is_per_file() => {
+                self.record_error(&rel, err.to_string());
+                Ok(())
+            }
+            result => result,
+        }
+    }
+
+    fn record_error(&mut self, rel: &Path, message: String) {
+        self.errors.push(ErrorRecord {
+            path: rel.to_string_lossy().replace('\\', "/"),
+            error: message,
+        });
+        self.counters.errors += 1;
+    }
+
+    /// Writes `ERRORS.txt` and `ERRORS.json` at the output root when anything failed.
+    fn write_error_manifest(&mut self, sink: &mut dyn OutputSink) -> Result<()> {
+        if self.errors.is_empty() {
+            return Ok(());
+        }
+        let text: String = self
+            .errors
+            .iter()
+            .map(|record| format!("{}: {}\n", record.path, record.error))
+            .collect();
+        sink.write_file(Path::new(ERRORS_TEXT), text.as_bytes())?;
+        let json = serde_json::to_string_pretty(&self.errors).map_err(|e| Error::Render {
+            message: e.to_string(),
+        })?;
+        sink.write_file(Path::new(ERRORS_JSON), (json + "\n").as_bytes())
+    }
+
+    fn write(&mut self, sink: &mut dyn OutputSink, event: DiffEvent) -> Result<()> {
+        match event {
+            DiffEvent::Equal { .. } => self.counters.same += 1,
+            DiffEvent::Added { rel, target } => {
+                let dst = self.output_name(&rel, NEW_MARKER);
+                sink.copy_file(&dst, &target)?;
+                self.preserve(sink, &dst, &target)?;
+                self.counters.new_files += 1;
+            }
+            DiffEvent::DirDeleted { rel, .. } => {
+                if !self.in_deleted_dir(&rel) {
+                    self.deleted_heads.push(rel.clone());
+                    self.counters.del_dirs += 1;
+                }
+                sink.create_dir(&self.output_name(&rel, DELETED_MARKER))?;
+            }
+            DiffEvent::Deleted { rel, base } => {
+                let dst = self.output_name(&rel, DELETED_MARKER);
+                sink.copy_file(&dst, &base)?;
+                self.preserve(sink, &dst, &base)?;
+                self.counters.del_files += 1;
+            }
+            DiffEvent::BinaryModified {
+                rel,
+                base,
+                target,
+                base_size,
+                target_size,
+                base_class,
+                target_class,
+                ..
+            } => {
+                let dst = self.output_name(&rel, MODIFIED_MARKER);
+                // Encoding reads both versions into memory, so large files are copied instead.
+                let max_size = self.opts.binary_delta_max_size;
+                let too_large = base_size.max(target_size) > max_size;
+                let delta = if self.opts.binary_delta && !too_large {
+                    Some(self.binary_delta(&base, &target)?)
+                } else {
+                    None
+                };
+                let strategy = match delta {
+                    // Only worth it when it saves space; the target is copied otherwise.
+                    Some((bytes, base_sha256)) if (bytes.len() as u64) < target_size => {
+                        let delta_dst = with_suffix(&dst, DELTA_SUFFIX);
+                        sink.write_file(&delta_dst, &bytes)?;
+                        let name = delta_dst.file_name().unwrap_or_default();
+                        format!(
+                            "binary delta against the base in {name:?} ({} bytes, base sha256 {base_sha256}); \
+rebuild with `bigdiff apply <base> {name:?} <output>`.",
+                            bytes.len()
+                        )
+                    }
+                    delta => {
+                        sink.copy_file(&dst, &target)?;
+                        self.preserve(sink, &dst, &target)?;
+                        match delta {
+                            Some((bytes, _)) => format!(
+                                "direct copy from target to '.modified' (a binary delta would take {} bytes).",
+                                bytes.len()
+                            ),
+                            None if self.opts.binary_delta => format!(
+                                "direct copy from target to '.modified' (over the binary delta limit of {max_size} bytes)."
+                            ),
+                            None => "direct copy from target to '.modified'.".into(),
+                        }
+                    }
+                };
+                self.counters.mod_binary += 1;
+
+                // Leave a small hint for the user explaining why we skipped a line diff.
+                let summary = match (base_class.is_text, target_class.is_text) {
+                    (true, true) => format!(
+                        "File too large for line diff (limit: {} bytes).",
+                        self.opts.max_text_size
+                    ),
+                    (true, false) => {
+                        "File changed from text to binary; no line diff across the change.".into()
+                    }
+                    (false, true) => {
+                        "File changed from binary to text; no line diff across the change.".into()
+                    }
+                    (false, false) => "File treated as binary.".into(),
+                };
+                let kind = |class: &Classification| if class.is_text { "text" } else { "binary" };
+                let note_content = format!(
+                    "{summary}\n\
+File: {:?}\n\
+Base (A): {} ({}), {base_size} bytes\n\
+Target (B): {} ({}), {target_size} bytes\n\
+Strategy: {strategy}\n",
+                    rel,
+                    kind(&base_class),
+                    base_class.reason,
+                    kind(&target_class),
+                    target_class.reason,
+                );
+                let note_content = match self.opts.binary_report {
+                    BinaryReport::Off => note_content,
+                    BinaryReport::Note => {
+                        note_content + "\n" + &self.binary_report(&base, &target)?
+                    }
+                    BinaryReport::Hexdiff => {
+                        let report = self.binary_report(&base, &target)?;
+                        let hexdiff = with_suffix(&dst, HEXDIFF_SIDECAR);
+                        sink.write_file(&hexdiff, report.as_bytes())?;
+                        let name = hexdiff.file_name().unwrap_or_default();
+                        note_content + &format!("Byte-level report: {name:?}\n")
+                    }
+                };
+                write_note(sink, &dst, note_content)?;
+            }
+            DiffEvent::Modified {
+                rel,
+                target,
+                base_text,
+                target_text,
+                base_encoding,
+                target_encoding,
+                structural,
+                rows,
+                ..
+            } => {
+                // Choose comment syntax from the member's own name and contents.
+                let member = self.archive_member(&rel);
+                let style = self.opts.styles.style_for_file(&member, &target);
+                let dst = self.output_name(&rel, MODIFIED_MARKER);
+                let encoding_note = encoding_note(&base_encoding, &target_encoding);
+
+                match rows {
+                    Some(changes) => {
+                        // The target stays loadable; the row-level report replaces the line diff.
+                        sink.copy_file(&dst, &target)?;
+                        let report = render_report(&changes, &self.opts.csv_keys)?;
+                        sink.write_file(&with_suffix(&dst, ROWS_REPORT), report.as_bytes())?;
+                        if let Some(note) = encoding_note {
+                            write_note(sink, &dst, note)?;
+                        }
+                    }
+                    None => {
+                        let text = TextOutput {
+                            path: &rel,
+                            rel: &member,
+                            b_file: &target,
+                            a_text: &base_text,
+                            b_text: &target_text,
+                            encoding: &target_encoding,
+                            encoding_note,
+                            header: &structural,
+                        };
+                        write_modified_text(&text, style, &dst, sink, self.opts)?;
+                    }
+                }
+                self.preserve(sink, &dst, &target)?;
+                self.counters.mod_text += 1;
+            }
+            DiffEvent::ArchiveModified { .. } => self.counters.mod_archive += 1,
+            DiffEvent::Warning { .. } => {}
+            DiffEvent::Error { rel, message } => self.record_error(&rel, message),
+        }
+        Ok(())
+    }
+
+    /// Delta of `target` against `base` for `--binary-delta`, with the base's SHA-256.
+    fn binary_delta(&self, base: &Path, target: &Path) -> Result<(Vec<u8>, String)> {
+        let base_bytes = fs::read(base).map_err(Error::io(base))?;
+        let target_bytes = fs::read(target).map_err(Error::io(target))?;
+        Ok((
+            encode(&base_bytes, &target_bytes),
+            sha256_bytes(&base_bytes),
+        ))
+    }
+
+    /// Byte-level report for `--binary-report`, within the configured budget.
+    fn binary_report(&self, base: &Path, target: &Path) -> Result<String> {
+        analyze(base, target)?.render(base, target, self.opts.binary_report_budget)
+    }
+
+    /// Applies `--preserve` to a `.new`, `.deleted` or `.modified` output.
+    fn preserve(&self, sink: &mut dyn OutputSink, dst: &Path, src: &Path) -> Result<()> {
+        if !self.opts.preserve.any() {
+            return Ok(());
+        }
+        sink.copy_metadata(dst, src, self.opts.preserve)
+    }
+
+    fn in_deleted_dir(&self, rel: &Path) -> bool {
+        self.deleted_heads.iter().any(|head| rel.starts_with(head))
+    }
+
+    /// Output path for `rel` with `marker` on its last name.
+    ///
+    /// Every source name is escaped (see [`crate::naming`]), folders inside a deleted folder
+    /// get `.deleted`, and `<archive>!` folders holding nested archive members stay as they are.
+    /// The result depends only on `rel`, so distinct entries never share an output.
+    fn output_name(&self, rel: &Path, marker: &str) -> PathBuf {
+        let parts: Vec<_> = rel.components().collect();
+        let mut source = PathBuf::new();
+        let mut out = PathBuf::new();
+        for (i, part) in parts.iter().enumerate() {
+            source.push(part);
+            let name = part.as_os_str();
+            let mut escaped = if i + 1 == parts.len() {
+                let mut escaped = escape_name(name);
+                escaped.push(marker);
+                escaped
+            } else if let Some(archive) = self.archive_folder(name) {
+                let mut escaped = escape_name(archive);
+                escaped.push(ARCHIVE_MARKER);
+                escaped
+            } else {
+                escape_name(name)
+            };
+            if i + 1 < parts.len() && self.in_deleted_dir(&source) {
+                escaped.push(DELETED_MARKER);
+            }
+            out.push(escaped);
+        }
+        out
+    }
+
+    /// The archive name behind a `<archive>!` folder of nested archive members.
+    fn archive_folder<'n>(&self, name: &'n OsStr) -> Option<&'n OsStr> {
+        if !self.opts.recurse_archives {
+            return None;
+        }
+        let archive = OsStr::new(name.to_str()?.strip_suffix(ARCHIVE_MARKER)?);
+        ArchiveKind::for_path(Path::new(archive)).map(|_| archive)
+    }
+
+    /// Path of `rel` inside its innermost nested archive (`x.zip!/dir/f` -> `dir/f`).
+    fn archive_member(&self, rel: &Path) -> PathBuf {
+        let parts: Vec<_> = rel.components().collect();
+        let cut = parts[..parts.len().saturating_sub(1)]
+            .iter()
+            .rposition(|part| self.archive_folder(part.as_os_str()).is_some())
+            .map_or(0, |i| i + 1);
+        parts[cut..].iter().collect()
+    }
+}
+
+/// Runs the enabled structural comparisons (`--semantic`, `--canonical-xml`) for `rel`.
+///
+/// `None` means no structural mode applies and the file gets a plain line diff; an error means
+/// one should have applied but a document did not parse.
+fn structural_changes(
+    rel: &Path,
+    a_text: &str,
+    b_text: &str,
+    opts: &Options,
+) -> Result<Option<Vec<String>>> {
+    if opts.semantic {
+        if let Some(changes) = semantic_diff(rel, a_text, b_text) {
+            return Ok(Some(changes?.iter().map(ToString::to_string).collect()));
+        }
+    }
+    if opts.canonical_xml {
+        if let Some(changes) = canonical_xml_diff(rel, a_text, b_text) {
+            return Ok(Some(changes.iter().map(ToString::to_string).collect()));
+        }
+    }
+    Ok(None)
+}
+
+/// Inputs for rendering one modified text file.
+struct TextOutput<'a> {
+    /// Reported path, `outer.zip!/member` inside nested archives.
+    path: &'a Path,
+    /// Path inside the innermost archive, named in sidecar headers.
+    rel: &'a Path,
+    b_file: &'a Path,
+    a_text: &'a str,
+    b_text: &'a str,
+    /// Encoding the annotated output is written in: the target's.
+    encoding: &'a TextEncoding,
+    /// NOTE lines describing non-UTF-8 encodings, if any.
+    encoding_note: Option<String>,
+    /// Summary lines (semantic key paths or XML locations) emitted before the diff.
+    header: &'a [String],
+}
+
+/// Writes the `.modified` output for a text file, inline or as a sidecar depending on `style`.
+fn write_modified_text(
+    text: &TextOutput,
+    style: &CommentStyle,
+    dst: &Path,
+    sink: &mut dyn OutputSink,
+    opts: &Options,
+) -> Result<()> {
+    let header: String = text
+        .header
+        .iter()
+        .map(|line| style.comment_line("SEMANTIC", &format!("{line}\n")))
+        .collect();
+
+    let mut note = String::new();
+    let mut annotated = None;
+    if !style.is_sidecar() {
+        let body = annotate_text(text.a_text, text.b_text, style);
+        if !opts.validate_annotations || validate_annotation(style, text.b_text, &body) {
+            annotated = Some(header.clone() + &body);
         } else {
-            let annotated = annotate_text_diff(a_file, b_file, &style, opts.normalize_eol)?;
-            fs::write(dst, annotated)?;
-            counters.mod_text += 1;
+            // Inline markers would change the file's comment structure; use a sidecar instead.
+            note = format!(
+                "Inline annotation skipped: the annotated output failed comment validation.\n\
+File: {:?}\n\
+Strategy: direct copy from target to '.modified', changes in the sidecar diff.\n",
+                text.path
+            );
+        }
+    }
+    if let Some(encoding_note) = &text.encoding_note {
+        note += encoding_note;
+    }
+
+    match annotated {
+        Some(annotated) => {
+            // Written back in the target's encoding so the output opens like the source.
+            let (bytes, lossy) = text.encoding.encode(&annotated);
+            if lossy {
+                note += &format!(
+                    "Characters without a {} representation were written as numeric character references.\n",
+                    text.encoding.encoding.name()
+                );
+            }
+            sink.write_file(dst, &bytes)?;
+        }
+        None => {
+            // Sidecar: keep `.modified` loadable by copying the target byte-for-byte.
+            let (suffix, mut sidecar) =
+                render_sidecar(text.rel, text.a_text, text.b_text, opts.sidecar_format)?;
+            if opts.sidecar_format == SidecarFormat::Diff {
+                // Text before the first `---` is ignored by patch tools.
+                sidecar = header + &sidecar;
+            }
+            sink.copy_file(dst, text.b_file)?;
+            sink.write_file(&with_suffix(dst, suffix), sidecar.as_bytes())?;
         }
     }
+    if !text.header.is_empty() {
+        let report = text.header.join("\n") + "\n";
+        sink.write_file(&with_suffix(dst, SEMANTIC_REPORT), report.as_bytes())?;
+    }
+    if !note.is_empty() {
+        write_note(sink, dst, note)?;
+    }
+    Ok(())
+}
+
+/// NOTE lines recording the detected encodings, when either side is not plain UTF-8.
+fn encoding_note(base: &TextEncoding, target: &TextEncoding) -> Option<String> {
+    if base.is_plain_utf8() && target.is_plain_utf8() {
+        return None;
+    }
+    Some(format!(
+        "Base encoding (A): {base}\n\
+Target encoding (B): {target}\n\
+Output encoding: {target}\n"
+    ))
+}
+
+/// Appends `suffix` to the final component of `path` (e.g. `a.json.modified` -> `a.json.modified.diff`).
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut out = path.to_path_buf();
+    if let Some(name) = out.file_name() {
+        let mut new_name = name.to_os_string();
+        new_name.push(suffix);
+        out.set_file_name(new_name);
+    }
+    out
+}
 
-    Ok(counters)
+/// Writes `<dst>.NOTE.txt` next to a `.modified` output.
+fn write_note(sink: &mut dyn OutputSink, dst: &Path, content: String) -> Result<()> {
+    sink.write_file(&with_suffix(dst, NOTE), content.as_bytes())
 }
@@ -0,0 +1,233 @@
+//
+// encoding.rs
+// BigDiff-rs
+//
+// Detects how text files are encoded (BOM, UTF-16 without BOM, UTF-8, Windows-1252 fallback or an `--encoding` override) so annotated outputs can be written back in the same encoding.
+//
+// Thales Matheus Mendonça Santos - November 2025
+//
+// Text encoding detection, round-trip decoding/encoding and the `--encoding` rules.
+use std::fmt;
+use std::path::Path;
+
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
+use glob::Pattern;
+
+use crate::error::{Error, Result};
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+const UTF16LE_BOM: &[u8] = b"\xFF\xFE";
+const UTF16BE_BOM: &[u8] = b"\xFE\xFF";
+
+// Bytes inspected when guessing BOM-less UTF-16.
+const SNIFF_BYTES: usize = 4096;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// How a text file is stored on disk.
+pub struct TextEncoding {
+    pub encoding: &'static Encoding,
+    /// Whether the file starts with a byte order mark.
+    pub bom: bool,
+}
+
+impl Default for TextEncoding {
+    fn default() -> Self {
+        TextEncoding {
+            encoding: UTF_8,
+            bom: false,
+        }
+    }
+}
+
+impl fmt::Display for TextEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.encoding.name())?;
+        if self.bom {
+            f.write_str(" with BOM")?;
+        }
+        Ok(())
+    }
+}
+
+impl TextEncoding {
+    /// Encodings that announce themselves: a BOM, or the NUL pattern of UTF-16 text.
+    ///
+    /// `None` means the bytes need the UTF-8 / fallback decision.
+    pub fn sniff(bytes: &[u8]) -> Option<TextEncoding> {
+        let with_bom = |encoding| TextEncoding {
+            encoding,
+            bom: true,
+        };
+        if bytes.starts_with(UTF8_BOM) {
+            return Some(with_bom(UTF_8));
+        }
+        if bytes.starts_with(UTF16LE_BOM) {
+            return Some(with_bom(UTF_16LE));
+        }
+        if bytes.starts_with(UTF16BE_BOM) {
+            return Some(with_bom(UTF_16BE));
+        }
+        utf16_without_bom(&bytes[..bytes.len().min(SNIFF_BYTES)]).map(|encoding| TextEncoding {
+            encoding,
+            bom: false,
+        })
+    }
+
+    /// Picks the encoding of a whole file. A BOM always wins; otherwise `forced` (from
+    /// `--encoding`) applies, then BOM-less UTF-16, UTF-8 and finally Windows-1252.
+    pub fn detect(bytes: &[u8], forced: Option<&'static Encoding>) -> TextEncoding {
+        if let Some(sniffed) = TextEncoding::sniff(bytes).filter(|e| e.bom) {
+            return sniffed;
+        }
+        if let Some(encoding) = forced {
+            return TextEncoding {
+                encoding,
+                bom: false,
+            };
+        }
+        if let Some(sniffed) = TextEncoding::sniff(bytes) {
+            return sniffed;
+        }
+        let encoding = if std::str::from_utf8(bytes).is_ok() {
+            UTF_8
+        } else {
+            WINDOWS_1252
+        };
+        TextEncoding {
+            encoding,
+            bom: false,
+        }
+    }
+
+    /// Whether this is plain UTF-8 without BOM, the encoding BigDiff writes by default.
+    pub fn is_plain_utf8(&self) -> bool {
+        *self == TextEncoding::default()
+    }
+
+    /// Decodes `bytes`, dropping the BOM if there is one.
+    pub fn decode(&self, bytes: &[u8]) -> String {
+        let body = if self.bom {
+            &bytes[self.bom_bytes().len().min(bytes.len())..]
+        } else {
+            bytes
+        };
+        let (text, _) = self.encoding.decode_without_bom_handling(body);
+        text.into_owned()
+    }
+
+    /// Encodes `text` back into this encoding, BOM included.
+    ///
+    /// The flag is `true` when some characters had no representation and were written as
+    /// numeric character references (single-byte encodings only).
+    pub fn encode(&self, text: &str) -> (Vec<u8>, bool) {
+        let mut out = if self.bom {
+            self.bom_bytes().to_vec()
+        } else {
+            Vec::new()
+        };
+        // encoding_rs only encodes into ASCII-compatible encodings; UTF-16 is done by hand.
+        let lossy = if self.encoding == UTF_16LE {
+            out.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+            false
+        } else if self.encoding == UTF_16BE {
+            out.extend(text.encode_utf16().flat_map(u16::to_be_bytes));
+            false
+        } else {
+            let (bytes, _, lossy) = self.encoding.encode(text);
+            out.extend_from_slice(&bytes);
+            lossy
+        };
+        (out, lossy)
+    }
+
+    fn bom_bytes(&self) -> &'static [u8] {
+        if self.encoding == UTF_16LE {
+            UTF16LE_BOM
+        } else if self.encoding == UTF_16BE {
+            UTF16BE_BOM
+        } else {
+            UTF8_BOM
+        }
+    }
+}
+
+// Mostly-ASCII UTF-16 has a NUL in every other byte: the high byte of each code unit.
+fn utf16_without_bom(bytes: &[u8]) -> Option<&'static Encoding> {
+    let units = bytes.len() / 2;
+    if units < 2 {
+        return None;
+    }
+    let zeros_at = |parity: usize| {
+        bytes
+            .iter()
+            .skip(parity)
+            .step_by(2)
+            .take(units)
+            .filter(|b| **b == 0)
+            .count()
+    };
+    let (even, odd) = (zeros_at(0), zeros_at(1));
+    let mostly = |count: usize| count * 10 >= units * 3;
+    let rarely = |count: usize| count * 20 <= units;
+    if mostly(odd) && rarely(even) {
+        Some(UTF_16LE)
+    } else if mostly(even) && rarely(odd) {
+        Some(UTF_16BE)
+    } else {
+        None
+    }
+}
+
+/// Looks up an encoding by WHATWG label (`utf-8`, `utf-16le`, `latin1`, `shift_jis`, ...).
+pub fn encoding_for_label(label: &str) -> Result<&'static Encoding> {
+    Encoding::for_label(label.trim().as_bytes()).ok_or_else(|| Error::UnknownEncoding {
+        label: label.to_string(),
+    })
+}
+
+#[derive(Debug, Default)]
+/// `--encoding` overrides: `LABEL` for every file, or `GLOB=LABEL` for matching ones.
+pub struct EncodingRules {
+    global: Option<&'static Encoding>,
+    globs: Vec<(Pattern, &'static Encoding)>,
+    specs: Vec<String>,
+}
+
+impl EncodingRules {
+    /// Parses the raw `--encoding` values. Later globs do not override earlier ones.
+    pub fn parse(specs: &[String]) -> Result<Self> {
+        let mut rules = EncodingRules {
+            specs: specs.to_vec(),
+            ..EncodingRules::default()
+        };
+        for spec in specs {
+            match spec.rsplit_once('=') {
+                Some((glob, label)) => {
+                    let pattern = Pattern::new(glob).map_err(|source| Error::InvalidGlob {
+                        pattern: glob.to_string(),
+                        source,
+                    })?;
+                    rules.globs.push((pattern, encoding_for_label(label)?));
+                }
+                None => rules.global = Some(encoding_for_label(spec)?),
+            }
+        }
+        Ok(rules)
+    }
+
+    /// The overriding encoding for `rel`, if any. Globs match the relative path or the file name.
+    pub fn for_path(&self, rel: &Path) -> Option<&'static Encoding> {
+        let s_rel = rel.to_string_lossy().replace('\\', "/");
+        let name = rel.file_name().and_then(|s| s.to_str()).unwrap_or("");
+        self.globs
+            .iter()
+            .find(|(pattern, _)| pattern.matches(&s_rel) || pattern.matches(name))
+            .map(|(_, encoding)| *encoding)
+            .or(self.global)
+    }
+
+    /// The values as given on the command line.
+    pub fn specs(&self) -> &[String] {
+        &self.specs
+    }
+}
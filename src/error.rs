@@ -0,0 +1,176 @@
+//
+// error.rs
+// BigDiff-rs
+//
+// Error type returned by the library so callers can tell unsafe outputs, bad configuration and I/O failures apart.
+//
+// Thales Matheus Mendonça Santos - November 2025
+//
+// Library error type: one variant per failure class, carrying the path or input involved.
+use std::io;
+use std::path::PathBuf;
+
+/// Everything the library can fail with.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// Reading or writing a file failed.
+    #[error("I/O error on {path:?}: {source}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    /// An `--ignore` pattern or a glob in the config does not compile.
+    #[error("Invalid glob pattern {pattern:?}: {source}")]
+    InvalidGlob {
+        pattern: String,
+        #[source]
+        source: glob::PatternError,
+    },
+
+    /// The config file is not valid TOML or has unknown keys.
+    #[error("Invalid config file {path:?}: {source}")]
+    Config {
+        path: PathBuf,
+        #[source]
+        source: toml::de::Error,
+    },
+
+    /// A config mapping refers to a style that is neither built in nor defined.
+    #[error("Unknown comment style in config: {name}")]
+    UnknownStyle { name: String },
+
+    /// An `--encoding` value names no known encoding.
+    #[error("Unknown encoding {label:?} (use a label such as utf-8, utf-16le, utf-16be or windows-1252)")]
+    UnknownEncoding { label: String },
+
+    /// A `[styles.<name>]` table sets neither a prefix nor an open/close pair.
+    #[error("Comment style {name:?} must set either `prefix` or both `open` and `close`")]
+    InvalidStyle { name: String },
+
+    /// The output root itself is a symlink.
+    #[error("Refusing to write into symlinked output root: {path:?}")]
+    SymlinkedOutputRoot { path: PathBuf },
+
+    /// A folder between the output root and a written file is a symlink.
+    #[error("Refusing to write through symlinked output path component: {path:?}")]
+    SymlinkedOutputComponent { path: PathBuf },
+
+    /// An output path resolves outside the output root.
+    #[error("Output target escapes output root: {path:?}")]
+    OutputOutsideRoot { path: PathBuf },
+
+    /// The output already exists and `--on-existing` is `error`.
+    #[error("Output already exists and is not empty: {path:?} (choose --on-existing=clean, merge or overwrite)")]
+    OutputExists { path: PathBuf },
+
+    /// `--on-existing=merge` found an existing file where an output goes.
+    #[error("Refusing to replace {path:?}: --on-existing=merge keeps existing files (use overwrite to replace them)")]
+    OutputClash { path: PathBuf },
+
+    /// An option the chosen `--output-format` cannot honor.
+    #[error("{option} is not supported with --output-format={format}")]
+    UnsupportedOutputOption { option: String, format: String },
+
+    /// `--preserve` reached a sink that keeps no per-file metadata.
+    #[error("Cannot preserve metadata of {path:?}: this output sink keeps no per-file metadata")]
+    MetadataUnsupported { path: PathBuf },
+
+    /// `--on-existing=clean` found no manifest telling which files BigDiff produced.
+    #[error("Refusing to clean {path:?}: it has no bigdiff-manifest.json from a previous run")]
+    UnknownOutputs { path: PathBuf },
+
+    /// A binary delta is malformed or does not belong to the given base.
+    #[error("Cannot apply delta {path:?}: {source}")]
+    Delta {
+        path: PathBuf,
+        #[source]
+        source: DeltaError,
+    },
+
+    /// `bigdiff-manifest.json` exists but cannot be parsed.
+    #[error("Invalid manifest {path:?}: {message}")]
+    Manifest { path: PathBuf, message: String },
+
+    /// An input archive could not be read or unpacked.
+    #[error("Failed to extract archive {path:?}: {source}")]
+    Archive {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    /// A structured document (JSON, YAML, TOML) does not parse.
+    #[error("Invalid {format} document: {message}")]
+    Parse {
+        format: &'static str,
+        message: String,
+    },
+
+    /// A CSV/TSV file cannot be compared by key (missing key column, duplicate key, bad record).
+    #[error("Keyed table comparison failed: {message}")]
+    Table { message: String },
+
+    /// A report or sidecar could not be serialized.
+    #[error("Failed to render report: {message}")]
+    Render { message: String },
+
+    /// Returned by a [`crate::visit_diff`] callback to stop the walk.
+    #[error(transparent)]
+    Visitor(Box<dyn std::error::Error + Send + Sync>),
+}
+
+/// Why a binary delta cannot be read or applied (see [`crate::delta`]).
+#[derive(Debug, thiserror::Error)]
+pub enum DeltaError {
+    /// The magic bytes are missing or name another format version.
+    #[error("not a BigDiff delta (or an unsupported version)")]
+    NotADelta,
+
+    /// The delta ends in the middle of its header or an instruction.
+    #[error("truncated delta")]
+    Truncated,
+
+    /// A varint does not fit in 64 bits.
+    #[error("malformed number")]
+    MalformedNumber,
+
+    /// An instruction tag this version does not know.
+    #[error("unknown instruction {tag}")]
+    UnknownInstruction { tag: u8 },
+
+    /// A copy instruction reads past the end of the base.
+    #[error("copy instruction outside the base")]
+    CopyOutsideBase,
+
+    /// The instructions produce more bytes than the header records.
+    #[error("the delta produces more than the recorded {size} bytes")]
+    TargetTooLong { size: u64 },
+
+    /// The base given is not the one the delta was made against.
+    #[error("the base does not match: expected {size} bytes with sha256 {sha256}")]
+    BaseMismatch { size: u64, sha256: String },
+
+    /// The rebuilt bytes differ from the recorded target.
+    #[error("the rebuilt file does not match the recorded target hash")]
+    TargetMismatch,
+}
+
+/// Result alias used throughout the library.
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl Error {
+    /// Adapter for `map_err` that attaches `path` to an I/O error.
+    pub(crate) fn io(path: impl Into<PathBuf>) -> impl FnOnce(io::Error) -> Error {
+        let path = path.into();
+        move |source| Error::Io { path, source }
+    }
+
+    /// Whether the error concerns one file only, so `--keep-going` may record it and go on.
+    ///
+    /// Output safety violations (symlinked or escaping output paths) always end the run.
+    pub(crate) fn is_per_file(&self) -> bool {
+        matches!(self, Error::Io { .. } | Error::Archive { .. })
+    }
+}
@@ -7,12 +7,35 @@
 // Thales Matheus Mendonça Santos - November 2025
 //
 // Public crate interface: re-export modules used by the binary and tests.
+pub mod archive;
+pub mod bindiff;
+pub mod classify;
 pub mod cli;
 pub mod comment;
+pub mod config;
+pub mod delta;
+pub mod detect;
 pub mod diff;
+pub mod encoding;
+pub mod error;
+pub mod manifest;
+pub mod naming;
 pub mod scanner;
+pub mod semantic;
+pub mod sink;
+pub mod staging;
+pub mod tabular;
 pub mod utils;
+pub mod xmlcanon;
 
+pub use archive::{ArchiveLimits, InputTree};
 pub use cli::{build_options, Args, Options};
-pub use diff::{run_bigdiff, Counters};
+pub use comment::StyleRegistry;
+pub use config::Config;
+pub use diff::{
+    plan_bigdiff, run_bigdiff, run_bigdiff_with_sink, visit_diff, Counters, DiffEvent, Plan,
+    PlannedAction,
+};
+pub use error::{DeltaError, Error, Result};
 pub use scanner::{scan_dir, ScanResult};
+pub use sink::{FsSink, MemorySink, NullSink, OutputFormat, OutputSink, PlannedOutput, Preserve};
@@ -11,78 +11,89 @@ use anyhow::{bail, Context, Result};
 use clap::Parser;
 use std::fs;
 
-use bigdiff::cli::{build_options, Args};
-use bigdiff::diff::run_bigdiff;
-use bigdiff::scanner::scan_dir;
+use bigdiff::cli::{build_options, load_styles, Args, Command, PlanFormat, StylesAction};
+use bigdiff::delta::{apply_file, apply_tree};
+use bigdiff::diff::{plan_bigdiff, run_bigdiff, Counters, Plan};
 use bigdiff::utils::ensure_output_target_safe;
+use bigdiff::{InputTree, OutputFormat, PlannedOutput};
 
 fn main() -> Result<()> {
     // Parse CLI arguments defined in `cli.rs`.
     let args = Args::parse();
 
-    // Resolve absolute, canonical paths so comparisons are stable.
-    let a_root = args.base_dir.canonicalize().context("Invalid base_dir")?;
-    let b_root = args
-        .target_dir
-        .canonicalize()
-        .context("Invalid target_dir")?;
-    let out_root = args.output_dir.clone();
+    if let Some(command) = &args.command {
+        return run_command(command);
+    }
+
+    // Clap enforces the positional directories whenever no subcommand is given.
+    let (Some(base_dir), Some(target_dir), Some(output_dir)) =
+        (&args.base_dir, &args.target_dir, &args.output_dir)
+    else {
+        bail!("base_dir, target_dir and output_dir are required.");
+    };
 
-    // Prevent self-comparison or writing results inside either input tree.
-    if a_root == b_root {
+    // Prevent self-comparison before expanding anything.
+    let a_input = base_dir.canonicalize().context("Invalid base_dir")?;
+    let b_input = target_dir.canonicalize().context("Invalid target_dir")?;
+    if a_input == b_input {
         bail!("base_dir and target_dir cannot be the same directory.");
     }
+
+    // Parse per-run options (ignore globs, normalization flags, etc.).
+    let opts = build_options(&args)?;
+
+    // Resolve absolute, canonical roots so comparisons are stable; archives are expanded to temp dirs.
+    let a_tree = InputTree::open(&a_input, &opts.archive_limits).context("Invalid base_dir")?;
+    let b_tree = InputTree::open(&b_input, &opts.archive_limits).context("Invalid target_dir")?;
+    let a_root = a_tree.root.clone();
+    let b_root = b_tree.root.clone();
+    let out_root = output_dir.clone();
+    opts.check_output_format()?;
+    let archive_output = opts.output_format != OutputFormat::Dir;
+
+    // Prevent writing results inside either input tree, or over an input archive itself.
     if out_root.exists() {
         let out_abs = out_root.canonicalize()?;
-        if out_abs == a_root
-            || out_abs == b_root
-            || out_abs.starts_with(&a_root)
-            || out_abs.starts_with(&b_root)
+        if [&a_root, &b_root, &a_input, &b_input]
+            .iter()
+            .any(|input| out_abs.starts_with(input))
         {
             bail!("output_dir cannot be inside base_dir/target_dir nor be equal to them.");
         }
+        if archive_output && out_abs.is_dir() {
+            bail!("output_dir must be a file path when --output-format is an archive.");
+        }
         ensure_output_target_safe(&out_root, &out_root)?;
     } else {
         ensure_output_target_safe(&out_root, &out_root).with_context(|| {
-            format!(
-                "Invalid output_dir before create_dir_all: {:?}",
-                args.output_dir
-            )
+            format!("Invalid output_dir before create_dir_all: {:?}", output_dir)
         })?;
-        // Ensure the output directory exists only after output path safety checks.
-        fs::create_dir_all(&out_root)
-            .with_context(|| format!("Failed to create output_dir: {:?}", args.output_dir))?;
+        // Ensure the output directory (or the archive's parent) exists only after output path safety checks.
+        let dir = if archive_output {
+            out_root.parent().filter(|p| !p.as_os_str().is_empty())
+        } else {
+            Some(out_root.as_path())
+        };
+        // Dry runs validate the destination but never create it.
+        if let Some(dir) = dir.filter(|_| !opts.dry_run) {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create output_dir: {:?}", output_dir))?;
+        }
     }
 
-    // Parse per-run options (ignore globs, normalization flags, etc.).
-    let opts = build_options(&args)?;
-
-    // Dry-run prints a summary without writing anything to disk.
+    // Dry-run classifies everything through the engine but discards the outputs.
     if opts.dry_run {
-        println!("== DRY RUN (Rust Simulation) ==");
-        let scan_a = scan_dir(&a_root, &opts.ignore_patterns);
-        let scan_b = scan_dir(&b_root, &opts.ignore_patterns);
-
-        let only_a = scan_a
-            .files
-            .keys()
-            .filter(|k| !scan_b.files.contains_key(*k))
-            .count();
-        let only_b = scan_b
-            .files
-            .keys()
-            .filter(|k| !scan_a.files.contains_key(*k))
-            .count();
-        let common = scan_a
-            .files
-            .keys()
-            .filter(|k| scan_b.files.contains_key(*k))
-            .count();
-
-        println!("Files only in Base (would be deleted): {}", only_a);
-        println!("Files only in Target (would be new): {}", only_b);
-        println!("Common files (would be checked): {}", common);
-        return Ok(());
+        let plan = plan_bigdiff(&a_root, &b_root, &opts)?;
+        match args.plan_format {
+            PlanFormat::Json => println!("{}", serde_json::to_string_pretty(&plan)?),
+            PlanFormat::Text => {
+                println!("== DRY RUN (Rust Simulation) ==");
+                print_plan(&plan);
+                print_counters(&plan.counters);
+                println!("Output would be at:   {:?}", out_root);
+            }
+        }
+        return check_errors(&plan.counters, "");
     }
 
     // Execute the main comparison workflow and collect counters.
@@ -90,13 +101,94 @@ fn main() -> Result<()> {
 
     // Provide a short, human-readable recap of what happened.
     println!("== BigDiff (Rust): Summary ==");
+    print_counters(&counters);
+    println!("Output at:            {:?}", out_root);
+
+    check_errors(&counters, "; see ERRORS.txt in the output")
+}
+
+/// Turns recorded per-file errors (`--keep-going`) into a non-zero exit status.
+fn check_errors(counters: &Counters, hint: &str) -> Result<()> {
+    if counters.errors > 0 {
+        bail!("{} file(s) could not be processed{hint}", counters.errors);
+    }
+    Ok(())
+}
+
+/// Prints each planned action followed by the outputs it would create.
+fn print_plan(plan: &Plan) {
+    for action in &plan.actions {
+        match &action.message {
+            Some(message) => println!("{:<17} {} ({message})", action.action, action.rel.display()),
+            None => println!("{:<17} {}", action.action, action.rel.display()),
+        }
+        for output in &action.outputs {
+            match output {
+                PlannedOutput::CreateDir { path } => println!("    mkdir  {}", path.display()),
+                PlannedOutput::Copy { path, from } => {
+                    println!("    copy   {} <- {}", path.display(), from.display())
+                }
+                PlannedOutput::Write { path, bytes } => {
+                    println!("    write  {} ({bytes} bytes)", path.display())
+                }
+            }
+        }
+    }
+}
+
+/// Prints one line per outcome category.
+fn print_counters(counters: &Counters) {
     println!("Equal (omitted):      {}", counters.same);
     println!("New (.new):           {}", counters.new_files);
     println!("Deleted (.deleted):   {}", counters.del_files);
     println!("Modified text:        {}", counters.mod_text);
     println!("Modified binary:      {}", counters.mod_binary);
+    println!("Modified archives:    {}", counters.mod_archive);
     println!("Deleted dirs:         {}", counters.del_dirs);
-    println!("Output at:            {:?}", out_root);
+    println!("Errors:               {}", counters.errors);
+}
 
-    Ok(())
+/// Handles auxiliary subcommands that do not run a comparison.
+fn run_command(command: &Command) -> Result<()> {
+    match command {
+        Command::Apply {
+            base,
+            delta,
+            output,
+        } => {
+            if delta.is_dir() {
+                let rebuilt = apply_tree(base, delta, output)?;
+                println!("Rebuilt {} file(s) into {:?}", rebuilt.len(), output);
+            } else {
+                apply_file(base, delta, output)?;
+                println!("Rebuilt {:?} from {:?}", output, delta);
+            }
+            Ok(())
+        }
+        Command::Styles {
+            action: StylesAction::List { config },
+        } => {
+            let registry = load_styles(config.as_ref())?;
+            println!(
+                "{:<10} {:<24} {:<12} {:<8} SYNTAX",
+                "MATCH", "PATTERN", "STYLE", "SOURCE"
+            );
+            for entry in registry.entries() {
+                let syntax = registry
+                    .style(&entry.style)
+                    .map(|s| s.to_string())
+                    .unwrap_or_default();
+                let source = if entry.builtin { "builtin" } else { "config" };
+                println!(
+                    "{:<10} {:<24} {:<12} {:<8} {}",
+                    entry.kind.to_string(),
+                    entry.pattern,
+                    entry.style,
+                    source,
+                    syntax
+                );
+            }
+            Ok(())
+        }
+    }
 }
@@ -0,0 +1,252 @@
+//
+// manifest.rs
+// BigDiff-rs
+//
+// Records which files a run produced in `bigdiff-manifest.json`, with their sources, hashes and the run options, so later runs and other tools can tell where each output came from.
+//
+// Thales Matheus Mendonça Santos - November 2025
+//
+// Output manifest: reading, writing, and the `--on-existing=clean` removal built on it.
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::cli::{BinaryReport, OnExisting, Options, SidecarFormat};
+use crate::error::{Error, Result};
+use crate::sink::{OutputFormat, Preserve};
+use crate::utils::{ensure_output_target_safe, sha256_file};
+
+/// Manifest file name at the output root.
+pub const MANIFEST_FILE: &str = "bigdiff-manifest.json";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+/// Everything a run wrote into the output root.
+pub struct Manifest {
+    pub tool: String,
+    pub version: String,
+    /// Absent in manifests written before options were recorded.
+    #[serde(default)]
+    pub options: Option<RunOptions>,
+    pub outputs: Vec<ManifestEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// The options that shaped the outputs.
+pub struct RunOptions {
+    pub normalize_eol: bool,
+    pub max_text_size: u64,
+    pub ignore: Vec<String>,
+    #[serde(default)]
+    pub encoding: Vec<String>,
+    #[serde(default)]
+    pub text: Vec<String>,
+    #[serde(default)]
+    pub binary: Vec<String>,
+    pub output_format: OutputFormat,
+    pub on_existing: OnExisting,
+    #[serde(default)]
+    pub preserve: Preserve,
+    pub keep_going: bool,
+    pub semantic: bool,
+    pub canonical_xml: bool,
+    pub recurse_archives: bool,
+    pub csv_keys: Vec<String>,
+    pub sidecar_format: SidecarFormat,
+    pub validate_annotations: bool,
+    #[serde(default)]
+    pub binary_report: BinaryReport,
+    #[serde(default)]
+    pub binary_report_budget: u64,
+    #[serde(default)]
+    pub binary_delta: bool,
+    #[serde(default)]
+    pub binary_delta_max_size: u64,
+}
+
+impl From<&Options> for RunOptions {
+    fn from(opts: &Options) -> Self {
+        RunOptions {
+            normalize_eol: opts.normalize_eol,
+            max_text_size: opts.max_text_size,
+            ignore: opts
+                .ignore_patterns
+                .iter()
+                .map(|p| p.as_str().to_string())
+                .collect(),
+            encoding: opts.encodings.specs().to_vec(),
+            text: opts.classifier.text_globs(),
+            binary: opts.classifier.binary_globs(),
+            output_format: opts.output_format,
+            on_existing: opts.on_existing,
+            preserve: opts.preserve,
+            keep_going: opts.keep_going,
+            semantic: opts.semantic,
+            canonical_xml: opts.canonical_xml,
+            recurse_archives: opts.recurse_archives,
+            csv_keys: opts.csv_keys.clone(),
+            sidecar_format: opts.sidecar_format,
+            validate_annotations: opts.validate_annotations,
+            binary_report: opts.binary_report,
+            binary_report_budget: opts.binary_report_budget,
+            binary_delta: opts.binary_delta,
+            binary_delta_max_size: opts.binary_delta_max_size,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryKind {
+    File,
+    Dir,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// One output path, relative to the output root.
+pub struct ManifestEntry {
+    pub path: PathBuf,
+    pub kind: EntryKind,
+    /// Event that produced the output (see [`crate::DiffEvent::kind`]), or `errors` for the
+    /// ERRORS files.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base: Option<SourceFile>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<SourceFile>,
+    /// Hash and size of the output itself; files only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// A base or target file an output was derived from.
+pub struct SourceFile {
+    /// Relative to its tree root; members of nested archives read `outer.zip!/member`.
+    pub path: PathBuf,
+    pub sha256: String,
+    pub size: u64,
+}
+
+impl ManifestEntry {
+    pub fn dir(path: PathBuf) -> Self {
+        ManifestEntry {
+            path,
+            kind: EntryKind::Dir,
+            category: None,
+            base: None,
+            target: None,
+            sha256: None,
+            size: None,
+        }
+    }
+
+    pub fn file(path: PathBuf, sha256: String, size: u64) -> Self {
+        ManifestEntry {
+            kind: EntryKind::File,
+            sha256: Some(sha256),
+            size: Some(size),
+            ..ManifestEntry::dir(path)
+        }
+    }
+}
+
+impl SourceFile {
+    /// Hashes the file at `path`, recording it as `rel`.
+    pub fn read(rel: &Path, path: &Path) -> Result<Self> {
+        SourceFile::with_sha256(rel, path, sha256_file(path)?)
+    }
+
+    /// Same as [`SourceFile::read`] when the digest of `path` is already known.
+    pub fn with_sha256(rel: &Path, path: &Path, sha256: String) -> Result<Self> {
+        let size = fs::metadata(path).map_err(Error::io(path))?.len();
+        Ok(SourceFile {
+            path: rel.to_path_buf(),
+            sha256,
+            size,
+        })
+    }
+}
+
+impl Manifest {
+    pub fn new(opts: &Options, outputs: Vec<ManifestEntry>) -> Self {
+        Manifest {
+            tool: "bigdiff".to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            options: Some(RunOptions::from(opts)),
+            outputs,
+        }
+    }
+
+    /// Reads the manifest of a previous run, if there is one.
+    pub fn load(out_root: &Path) -> Result<Option<Self>> {
+        let path = out_root.join(MANIFEST_FILE);
+        ensure_output_target_safe(out_root, &path)?;
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let raw = fs::read_to_string(&path).map_err(Error::io(&path))?;
+        let manifest = serde_json::from_str(&raw).map_err(|e| Error::Manifest {
+            path: path.clone(),
+            message: e.to_string(),
+        })?;
+        Ok(Some(manifest))
+    }
+
+    /// Pretty-printed JSON, as written to [`MANIFEST_FILE`].
+    pub fn to_json(&self) -> Result<String> {
+        let json = serde_json::to_string_pretty(self).map_err(|e| Error::Render {
+            message: e.to_string(),
+        })?;
+        Ok(json + "\n")
+    }
+
+    /// Writes the manifest at the output root.
+    pub fn save(&self, out_root: &Path) -> Result<()> {
+        let path = out_root.join(MANIFEST_FILE);
+        ensure_output_target_safe(out_root, &path)?;
+        fs::write(&path, self.to_json()?).map_err(Error::io(path))
+    }
+}
+
+/// Fails unless `path`, read from a manifest, is a plain relative path (no `..`, no root).
+pub(crate) fn check_relative(path: &Path) -> Result<()> {
+    if path.components().all(|c| matches!(c, Component::Normal(_))) {
+        Ok(())
+    } else {
+        Err(Error::OutputOutsideRoot {
+            path: path.to_path_buf(),
+        })
+    }
+}
+
+/// Removes the files and (now empty) folders listed in the previous manifest, then the
+/// manifest itself. Paths are checked with the usual symlink rules; anything not listed stays.
+pub fn clean_outputs(out_root: &Path, manifest: &Manifest) -> Result<()> {
+    let mut dirs = Vec::new();
+    for entry in &manifest.outputs {
+        // A tampered manifest must not reach outside the output root.
+        check_relative(&entry.path)?;
+        let path = out_root.join(&entry.path);
+        ensure_output_target_safe(out_root, &path)?;
+        match entry.kind {
+            EntryKind::Dir => dirs.push(path),
+            EntryKind::File if path.is_file() => {
+                fs::remove_file(&path).map_err(Error::io(&path))?
+            }
+            EntryKind::File => {}
+        }
+    }
+
+    // Deepest first; folders that still hold other files are left alone.
+    dirs.sort_by_key(|d| std::cmp::Reverse(d.components().count()));
+    for dir in dirs {
+        let _ = fs::remove_dir(dir);
+    }
+
+    let path = out_root.join(MANIFEST_FILE);
+    fs::remove_file(&path).map_err(Error::io(path))
+}
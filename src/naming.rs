@@ -0,0 +1,121 @@
+//
+// naming.rs
+// BigDiff-rs
+//
+// Output naming scheme: source names are escaped so they can never look like a marker, a companion file or a reserved name, which keeps distinct sources on distinct outputs whatever the scan order.
+//
+// Thales Matheus Mendonça Santos - November 2025
+//
+// Markers, companion suffixes, reserved names and the escaping that keeps them apart.
+use std::ffi::{OsStr, OsString};
+use std::path::Path;
+
+use crate::archive::{ArchiveKind, ARCHIVE_MARKER};
+use crate::manifest::MANIFEST_FILE;
+use crate::staging::{COMPLETE_MARKER, JOURNAL_FILE, STAGING_DIR};
+
+/// Appended to files that only exist in the target tree.
+pub const NEW_MARKER: &str = ".new";
+/// Appended to files and folders that only exist in the base tree.
+pub const DELETED_MARKER: &str = ".deleted";
+/// Appended to files that differ.
+pub const MODIFIED_MARKER: &str = ".modified";
+
+/// Unified diff sidecar, next to a `.modified` output.
+pub const DIFF_SIDECAR: &str = ".diff";
+/// JSON hunk sidecar, next to a `.modified` output.
+pub const HUNKS_SIDECAR: &str = ".hunks.json";
+/// Row-level CSV/TSV report, next to a `.modified` output.
+pub const ROWS_REPORT: &str = ".rows.csv";
+/// Structural change list (`--semantic`, `--canonical-xml`), next to a `.modified` output.
+pub const SEMANTIC_REPORT: &str = ".SEMANTIC.txt";
+/// Byte-level report (`--binary-report hexdiff`), next to a binary `.modified` output.
+pub const HEXDIFF_SIDECAR: &str = ".hexdiff";
+/// Binary delta against the base (`--binary-delta`), in place of a binary `.modified` output.
+pub const DELTA_SUFFIX: &str = ".bdelta";
+/// Explanation of how a `.modified` output was produced.
+pub const NOTE: &str = ".NOTE.txt";
+
+/// Every suffix written after `.modified`.
+pub const COMPANIONS: &[&str] = &[
+    DIFF_SIDECAR,
+    HUNKS_SIDECAR,
+    ROWS_REPORT,
+    SEMANTIC_REPORT,
+    HEXDIFF_SIDECAR,
+    DELTA_SUFFIX,
+    NOTE,
+];
+
+/// Error list written at the output root by `--keep-going`.
+pub const ERRORS_TEXT: &str = "ERRORS.txt";
+/// Same list as JSON.
+pub const ERRORS_JSON: &str = "ERRORS.json";
+
+/// Names BigDiff uses for its own files at the output (or staging) root.
+pub const RESERVED_NAMES: &[&str] = &[
+    ERRORS_TEXT,
+    ERRORS_JSON,
+    MANIFEST_FILE,
+    STAGING_DIR,
+    JOURNAL_FILE,
+    COMPLETE_MARKER,
+];
+
+/// Appended to a source name that would otherwise be mistaken for an output name.
+pub const ESCAPE: &str = "~";
+
+/// Escapes one source file or folder name.
+///
+/// Names ending with a marker, a `.modified` companion, the archive marker or `~`, and the
+/// reserved names, get one extra `~`; every other name is unchanged. Removing a trailing `~`
+/// undoes it, so distinct names stay distinct, and an escaped name followed by a marker can
+/// never equal another escaped name.
+pub fn escape_name(name: &OsStr) -> OsString {
+    let mut escaped = name.to_os_string();
+    if needs_escape(name) {
+        escaped.push(ESCAPE);
+    }
+    escaped
+}
+
+fn needs_escape(name: &OsStr) -> bool {
+    let bytes = name.as_encoded_bytes();
+    let ends_with = |suffix: &str| bytes.ends_with(suffix.as_bytes());
+    let companion = |companion: &&str| {
+        bytes
+            .strip_suffix(companion.as_bytes())
+            .is_some_and(|rest| rest.ends_with(MODIFIED_MARKER.as_bytes()))
+    };
+    [
+        NEW_MARKER,
+        DELETED_MARKER,
+        MODIFIED_MARKER,
+        ARCHIVE_MARKER,
+        ESCAPE,
+    ]
+    .into_iter()
+    .any(ends_with)
+        || COMPANIONS.iter().any(companion)
+        || RESERVED_NAMES
+            .iter()
+            .any(|reserved| bytes == reserved.as_bytes())
+}
+
+/// Escapes a source name that reads as a nested archive folder (`app.jar!`, `app.jar!~`, ...).
+///
+/// With `--recurse-archives`, `<archive>!` in a reported path always stands for the members of
+/// that archive, so a real file or folder named like one is reported with one extra `~`, as
+/// [`escape_name`] does for reserved names. Its output name is then escaped again like any name
+/// ending with `~`.
+pub fn escape_archive_folder(name: &OsStr) -> OsString {
+    let mut escaped = name.to_os_string();
+    let archive = name
+        .to_str()
+        .map(|name| name.trim_end_matches(ESCAPE))
+        .and_then(|name| name.strip_suffix(ARCHIVE_MARKER));
+    if archive.is_some_and(|archive| ArchiveKind::for_path(Path::new(archive)).is_some()) {
+        escaped.push(ESCAPE);
+    }
+    escaped
+}
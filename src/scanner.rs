@@ -7,18 +7,25 @@
 // Thales Matheus Mendonça Santos - November 2025
 //
 // Filesystem scanning utilities that collect relative paths while honoring ignore rules.
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet};
 use std::path::{Path, PathBuf};
 
 use glob::Pattern;
 use walkdir::WalkDir;
 
+use crate::error::Error;
+
 #[derive(Debug)]
 /// Captures a directory traversal: absolute root plus relative files/dirs found.
+///
+/// Both collections are sorted by relative path, so everything built on a scan runs in the
+/// same order on every run.
 pub struct ScanResult {
-    pub files: HashMap<PathBuf, PathBuf>, // rel -> abs
-    pub dirs: HashSet<PathBuf>,           // rel
+    pub files: BTreeMap<PathBuf, PathBuf>, // rel -> abs
+    pub dirs: BTreeSet<PathBuf>,           // rel
     pub root: PathBuf,
+    /// Entries that could not be read, with their relative path, in walk order.
+    pub errors: Vec<(PathBuf, Error)>,
 }
 
 fn is_ignored(rel: &Path, patterns: &[Pattern]) -> bool {
@@ -37,8 +44,9 @@ fn is_ignored(rel: &Path, patterns: &[Pattern]) -> bool {
 }
 
 pub fn scan_dir(root: &Path, patterns: &[Pattern]) -> ScanResult {
-    let mut files = HashMap::new();
-    let mut dirs = HashSet::new();
+    let mut files = BTreeMap::new();
+    let mut dirs = BTreeSet::new();
+    let mut errors = Vec::new();
 
     // Walk the tree non-recursively following only real files/directories.
     let walker = WalkDir::new(root).follow_links(false).into_iter();
@@ -55,19 +63,33 @@ pub fn scan_dir(root: &Path, patterns: &[Pattern]) -> ScanResult {
             true
         }
     }) {
-        if let Ok(entry) = entry {
-            let path = entry.path();
-            if let Ok(rel) = path.strip_prefix(root) {
-                if rel == Path::new("") {
-                    continue;
-                }
+        match entry {
+            Ok(entry) => {
+                let path = entry.path();
+                if let Ok(rel) = path.strip_prefix(root) {
+                    if rel == Path::new("") {
+                        continue;
+                    }
 
-                if path.is_dir() {
-                    dirs.insert(rel.to_path_buf());
-                } else if path.is_file() {
-                    files.insert(rel.to_path_buf(), path.to_path_buf());
+                    if path.is_dir() {
+                        dirs.insert(rel.to_path_buf());
+                    } else if path.is_file() {
+                        files.insert(rel.to_path_buf(), path.to_path_buf());
+                    }
                 }
             }
+            // An unreadable folder is recorded, not silently left out of the comparison.
+            Err(err) => {
+                let path = err.path().unwrap_or(root).to_path_buf();
+                let rel = path.strip_prefix(root).unwrap_or(&path).to_path_buf();
+                errors.push((
+                    rel,
+                    Error::Io {
+                        path,
+                        source: err.into(),
+                    },
+                ));
+            }
         }
     }
 
@@ -75,5 +97,6 @@ pub fn scan_dir(root: &Path, patterns: &[Pattern]) -> ScanResult {
         files,
         dirs,
         root: root.to_path_buf(),
+        errors,
     }
 }
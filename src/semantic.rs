@@ -0,0 +1,191 @@
+//
+// semantic.rs
+// BigDiff-rs
+//
+// Structural comparison for JSON, YAML and TOML documents: parses both sides and reports changes as key paths instead of lines.
+//
+// Thales Matheus Mendonça Santos - November 2025
+//
+// Semantic diff for config formats, so reformatting or key reordering is not reported as a change.
+use std::fmt;
+use std::path::Path;
+
+use serde_json::Value;
+
+use crate::error::{Error, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Structured formats understood by the semantic mode.
+pub enum Format {
+    Json,
+    Yaml,
+    Toml,
+}
+
+impl Format {
+    /// Picks the format from the file extension.
+    pub fn for_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_lowercase();
+        match ext.as_str() {
+            "json" => Some(Format::Json),
+            "yaml" | "yml" => Some(Format::Yaml),
+            "toml" => Some(Format::Toml),
+            _ => None,
+        }
+    }
+
+    /// Parses a document into a format-neutral tree (object keys are kept sorted).
+    pub fn parse(self, text: &str) -> Result<Value> {
+        let parsed = match self {
+            Format::Json => serde_json::from_str(text).map_err(|e| e.to_string()),
+            Format::Yaml => serde_yaml::from_str(text).map_err(|e| e.to_string()),
+            Format::Toml => toml::from_str(text)
+                .map(toml_to_json)
+                .map_err(|e| e.to_string()),
+        };
+        parsed.map_err(|message| Error::Parse {
+            format: self.name(),
+            message,
+        })
+    }
+
+    /// Human-readable format name used in error messages.
+    pub fn name(self) -> &'static str {
+        match self {
+            Format::Json => "JSON",
+            Format::Yaml => "YAML",
+            Format::Toml => "TOML",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// One difference between two parsed documents, addressed by a `$.a.b[0]` style path.
+pub enum SemanticChange {
+    Added {
+        path: String,
+        value: Value,
+    },
+    Removed {
+        path: String,
+        value: Value,
+    },
+    Changed {
+        path: String,
+        old: Value,
+        new: Value,
+    },
+}
+
+impl fmt::Display for SemanticChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SemanticChange::Added { path, value } => write!(f, "{path}: added {value}"),
+            SemanticChange::Removed { path, value } => write!(f, "{path}: removed {value}"),
+            SemanticChange::Changed { path, old, new } => write!(f, "{path}: {old} -> {new}"),
+        }
+    }
+}
+
+// Datetimes become their TOML spelling; serde would expose them as a private wrapper table.
+fn toml_to_json(value: toml::Value) -> Value {
+    match value {
+        toml::Value::String(s) => Value::String(s),
+        toml::Value::Integer(i) => Value::from(i),
+        toml::Value::Float(f) => serde_json::Number::from_f64(f)
+            .map_or_else(|| Value::String(f.to_string()), Value::Number),
+        toml::Value::Boolean(b) => Value::Bool(b),
+        toml::Value::Datetime(dt) => Value::String(dt.to_string()),
+        toml::Value::Array(items) => Value::Array(items.into_iter().map(toml_to_json).collect()),
+        toml::Value::Table(table) => Value::Object(
+            table
+                .into_iter()
+                .map(|(key, value)| (key, toml_to_json(value)))
+                .collect(),
+        ),
+    }
+}
+
+/// Parses both texts and lists their structural differences.
+///
+/// Returns `None` when the path is not a structured format, and the parse error when either
+/// side cannot be compared structurally (invalid syntax, multi-document YAML, non-string keys),
+/// so the caller can report it and fall back to a line diff.
+pub fn semantic_diff(
+    rel: &Path,
+    a_text: &str,
+    b_text: &str,
+) -> Option<Result<Vec<SemanticChange>>> {
+    let format = Format::for_path(rel)?;
+    let parsed = format
+        .parse(a_text)
+        .and_then(|a| Ok((a, format.parse(b_text)?)));
+    Some(parsed.map(|(a, b)| {
+        let mut changes = Vec::new();
+        compare_values("$", &a, &b, &mut changes);
+        changes
+    }))
+}
+
+/// Recursively compares two values, appending every difference under `path`.
+pub fn compare_values(path: &str, a: &Value, b: &Value, changes: &mut Vec<SemanticChange>) {
+    match (a, b) {
+        (Value::Object(a_map), Value::Object(b_map)) => {
+            for (key, a_val) in a_map {
+                let child = object_path(path, key);
+                match b_map.get(key) {
+                    Some(b_val) => compare_values(&child, a_val, b_val, changes),
+                    None => changes.push(SemanticChange::Removed {
+                        path: child,
+                        value: a_val.clone(),
+                    }),
+                }
+            }
+            for (key, b_val) in b_map {
+                if !a_map.contains_key(key) {
+                    changes.push(SemanticChange::Added {
+                        path: object_path(path, key),
+                        value: b_val.clone(),
+                    });
+                }
+            }
+        }
+        (Value::Array(a_items), Value::Array(b_items)) => {
+            for (i, a_val) in a_items.iter().enumerate() {
+                let child = format!("{path}[{i}]");
+                match b_items.get(i) {
+                    Some(b_val) => compare_values(&child, a_val, b_val, changes),
+                    None => changes.push(SemanticChange::Removed {
+                        path: child,
+                        value: a_val.clone(),
+                    }),
+                }
+            }
+            for (i, b_val) in b_items.iter().enumerate().skip(a_items.len()) {
+                changes.push(SemanticChange::Added {
+                    path: format!("{path}[{i}]"),
+                    value: b_val.clone(),
+                });
+            }
+        }
+        _ if a != b => changes.push(SemanticChange::Changed {
+            path: path.to_string(),
+            old: a.clone(),
+            new: b.clone(),
+        }),
+        _ => {}
+    }
+}
+
+// Plain identifiers use dot notation; anything else is quoted in brackets.
+fn object_path(parent: &str, key: &str) -> String {
+    let simple = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if simple {
+        format!("{parent}.{key}")
+    } else {
+        format!("{parent}[{}]", Value::String(key.to_string()))
+    }
+}
@@ -0,0 +1,418 @@
+//
+// sink.rs
+// BigDiff-rs
+//
+// Output sinks that receive every file and folder BigDiff produces, either on disk or streamed into a zip/tar archive.
+//
+// Thales Matheus Mendonça Santos - November 2025
+//
+// Output abstraction so the diff engine does not care where results end up.
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Component, Path, PathBuf};
+
+use clap::ValueEnum;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+use crate::utils::ensure_output_target_safe;
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// Where the output tree is written.
+pub enum OutputFormat {
+    /// Plain directory tree
+    #[default]
+    Dir,
+    /// Single `.zip` file
+    Zip,
+    /// Single uncompressed `.tar` file
+    Tar,
+    /// Single gzip-compressed tar file
+    #[value(name = "tar.gz")]
+    #[serde(rename = "tar.gz")]
+    TarGz,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+/// Source metadata carried over to `.new`, `.deleted` and `.modified` outputs (`--preserve`).
+pub struct Preserve {
+    /// Permission bits (`+x` on Unix, read-only elsewhere).
+    pub mode: bool,
+    /// Last modification time.
+    pub mtime: bool,
+}
+
+impl Preserve {
+    pub fn any(self) -> bool {
+        self.mode || self.mtime
+    }
+}
+
+/// Destination for everything BigDiff writes. Paths are relative to the output root.
+pub trait OutputSink {
+    /// Ensures a (possibly empty) directory exists in the output.
+    fn create_dir(&mut self, rel: &Path) -> Result<()>;
+
+    /// Writes a file with the given contents.
+    fn write_file(&mut self, rel: &Path, contents: &[u8]) -> Result<()>;
+
+    /// Copies `src` from an input tree into the output.
+    fn copy_file(&mut self, rel: &Path, src: &Path) -> Result<()>;
+
+    /// Gives the written output `rel` the metadata of `src` selected by `preserve`.
+    ///
+    /// Sinks without per-file metadata refuse it rather than drop it silently.
+    fn copy_metadata(&mut self, rel: &Path, _src: &Path, _preserve: Preserve) -> Result<()> {
+        Err(Error::MetadataUnsupported {
+            path: rel.to_path_buf(),
+        })
+    }
+
+    /// Flushes buffered data and writes archive trailers; no writes may follow.
+    fn finish(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Opens the sink for `format` at `out_root` (a directory, or the archive file to create).
+pub fn open_sink(format: OutputFormat, out_root: &Path) -> Result<Box<dyn OutputSink>> {
+    Ok(match format {
+        OutputFormat::Dir => Box::new(FsSink::new(out_root)),
+        _ => Box::new(ArchiveSink::create(out_root, format)?),
+    })
+}
+
+/// Writes into a directory on disk, refusing to follow symlinks inside the output root.
+pub struct FsSink {
+    root: PathBuf,
+}
+
+impl FsSink {
+    pub fn new(root: &Path) -> Self {
+        FsSink {
+            root: root.to_path_buf(),
+        }
+    }
+
+    // Validates the destination and creates its parent folders.
+    fn prepare(&self, rel: &Path) -> Result<PathBuf> {
+        let dst = self.root.join(rel);
+        if let Some(parent) = dst.parent() {
+            ensure_output_target_safe(&self.root, parent)?;
+            fs::create_dir_all(parent).map_err(Error::io(parent))?;
+        }
+        ensure_output_target_safe(&self.root, &dst)?;
+        Ok(dst)
+    }
+}
+
+impl OutputSink for FsSink {
+    fn create_dir(&mut self, rel: &Path) -> Result<()> {
+        let dst = self.root.join(rel);
+        ensure_output_target_safe(&self.root, &dst)?;
+        fs::create_dir_all(&dst).map_err(Error::io(dst))
+    }
+
+    fn write_file(&mut self, rel: &Path, contents: &[u8]) -> Result<()> {
+        let dst = self.prepare(rel)?;
+        fs::write(&dst, contents).map_err(Error::io(dst))
+    }
+
+    fn copy_file(&mut self, rel: &Path, src: &Path) -> Result<()> {
+        let dst = self.prepare(rel)?;
+        fs::copy(src, &dst).map_err(Error::io(dst))?;
+        Ok(())
+    }
+
+    fn copy_metadata(&mut self, rel: &Path, src: &Path, preserve: Preserve) -> Result<()> {
+        let dst = self.root.join(rel);
+        ensure_output_target_safe(&self.root, &dst)?;
+        let meta = fs::metadata(src).map_err(Error::io(src))?;
+        // `fs::copy` may already have made the output read-only, so the times are set through
+        // a read-only handle.
+        if preserve.mtime {
+            let modified = meta.modified().map_err(Error::io(src))?;
+            File::open(&dst)
+                .and_then(|file| file.set_modified(modified))
+                .map_err(Error::io(&dst))?;
+        }
+        if preserve.mode {
+            fs::set_permissions(&dst, meta.permissions()).map_err(Error::io(&dst))?;
+        }
+        Ok(())
+    }
+}
+
+/// Keeps every output in memory; handy for tests and for callers that post-process results.
+#[derive(Debug, Default)]
+pub struct MemorySink {
+    files: BTreeMap<PathBuf, Vec<u8>>,
+    dirs: BTreeSet<PathBuf>,
+}
+
+impl MemorySink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Written files keyed by output-relative path.
+    pub fn files(&self) -> &BTreeMap<PathBuf, Vec<u8>> {
+        &self.files
+    }
+
+    /// Directories explicitly created (e.g. for deleted folders).
+    pub fn dirs(&self) -> &BTreeSet<PathBuf> {
+        &self.dirs
+    }
+
+    /// Contents of one output file, if it was produced.
+    pub fn get(&self, rel: impl AsRef<Path>) -> Option<&[u8]> {
+        self.files.get(rel.as_ref()).map(Vec::as_slice)
+    }
+}
+
+impl OutputSink for MemorySink {
+    fn create_dir(&mut self, rel: &Path) -> Result<()> {
+        self.dirs.insert(rel.to_path_buf());
+        Ok(())
+    }
+
+    fn write_file(&mut self, rel: &Path, contents: &[u8]) -> Result<()> {
+        self.files.insert(rel.to_path_buf(), contents.to_vec());
+        Ok(())
+    }
+
+    fn copy_file(&mut self, rel: &Path, src: &Path) -> Result<()> {
+        let contents = fs::read(src).map_err(Error::io(src))?;
+        self.files.insert(rel.to_path_buf(), contents);
+        Ok(())
+    }
+}
+
+/// One output a [`NullSink`] would have produced.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "op", rename_all = "kebab-case")]
+pub enum PlannedOutput {
+    /// A folder created in the output.
+    CreateDir { path: PathBuf },
+    /// A file copied from an input tree.
+    Copy { path: PathBuf, from: PathBuf },
+    /// A generated file (annotated copy, sidecar, note, report).
+    Write { path: PathBuf, bytes: usize },
+}
+
+impl PlannedOutput {
+    /// Output-relative destination.
+    pub fn path(&self) -> &Path {
+        match self {
+            PlannedOutput::CreateDir { path }
+            | PlannedOutput::Copy { path, .. }
+            | PlannedOutput::Write { path, .. } => path,
+        }
+    }
+}
+
+/// Discards contents and records what would have been written. Powers `--dry-run`.
+#[derive(Debug, Default)]
+pub struct NullSink {
+    log: Vec<PlannedOutput>,
+}
+
+impl NullSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the outputs recorded since the previous call, in order.
+    pub fn take_log(&mut self) -> Vec<PlannedOutput> {
+        std::mem::take(&mut self.log)
+    }
+
+    fn record(&mut self, output: PlannedOutput) {
+        self.log.push(output);
+    }
+}
+
+impl OutputSink for NullSink {
+    fn create_dir(&mut self, rel: &Path) -> Result<()> {
+        self.record(PlannedOutput::CreateDir {
+            path: rel.to_path_buf(),
+        });
+        Ok(())
+    }
+
+    fn write_file(&mut self, rel: &Path, contents: &[u8]) -> Result<()> {
+        self.record(PlannedOutput::Write {
+            path: rel.to_path_buf(),
+            bytes: contents.len(),
+        });
+        Ok(())
+    }
+
+    fn copy_file(&mut self, rel: &Path, src: &Path) -> Result<()> {
+        self.record(PlannedOutput::Copy {
+            path: rel.to_path_buf(),
+            from: src.to_path_buf(),
+        });
+        Ok(())
+    }
+
+    // A dry run plans the files; their metadata would follow on a real run.
+    fn copy_metadata(&mut self, _rel: &Path, _src: &Path, _preserve: Preserve) -> Result<()> {
+        Ok(())
+    }
+}
+
+enum ArchiveWriter {
+    Zip(zip::ZipWriter<BufWriter<File>>),
+    Tar(tar::Builder<BufWriter<File>>),
+    TarGz(tar::Builder<GzEncoder<BufWriter<File>>>),
+}
+
+/// Streams the output tree into a single zip or tar archive.
+pub struct ArchiveSink {
+    path: PathBuf,
+    /// Written while the run is in progress, renamed to `path` by `finish`.
+    partial: PathBuf,
+    writer: Option<ArchiveWriter>,
+    entries: HashSet<PathBuf>,
+}
+
+/// Timestamp of every archive entry: 1980-01-01, the earliest a zip can hold (and the zip
+/// writer's default). Fixed so identical inputs give byte-identical archives.
+const ARCHIVE_MTIME: u64 = 315_532_800;
+
+impl ArchiveSink {
+    /// Starts `<path>.partial` after the usual symlink checks; `finish` renames it to `path`.
+    pub fn create(path: &Path, format: OutputFormat) -> Result<Self> {
+        let mut partial = path.as_os_str().to_os_string();
+        partial.push(".partial");
+        let partial = PathBuf::from(partial);
+        ensure_output_target_safe(path, path)?;
+        ensure_output_target_safe(&partial, &partial)?;
+        let file = BufWriter::new(File::create(&partial).map_err(Error::io(&partial))?);
+        let writer = match format {
+            OutputFormat::Zip => ArchiveWriter::Zip(zip::ZipWriter::new(file)),
+            OutputFormat::Tar => ArchiveWriter::Tar(tar::Builder::new(file)),
+            OutputFormat::TarGz => ArchiveWriter::TarGz(tar::Builder::new(GzEncoder::new(
+                file,
+                Compression::default(),
+            ))),
+            OutputFormat::Dir => unreachable!("directory output does not use an archive sink"),
+        };
+        Ok(ArchiveSink {
+            path: path.to_path_buf(),
+            partial,
+            writer: Some(writer),
+            entries: HashSet::new(),
+        })
+    }
+
+    fn writer(&mut self) -> io::Result<&mut ArchiveWriter> {
+        self.writer
+            .as_mut()
+            .ok_or_else(|| io::Error::other("archive output already finished"))
+    }
+
+    fn append(&mut self, rel: &Path, size: u64, mut data: impl io::Read) -> Result<()> {
+        let name = archive_name(rel)?;
+        let written = self.writer().and_then(|writer| match writer {
+            ArchiveWriter::Zip(zip) => {
+                zip.start_file(name, zip::write::SimpleFileOptions::default())?;
+                io::copy(&mut data, zip).map(drop)
+            }
+            ArchiveWriter::Tar(tar) => append_tar(tar, &name, size, data),
+            ArchiveWriter::TarGz(tar) => append_tar(tar, &name, size, data),
+        });
+        written.map_err(Error::io(&self.partial))?;
+        self.entries.insert(rel.to_path_buf());
+        Ok(())
+    }
+}
+
+fn tar_header(kind: tar::EntryType, size: u64, mode: u32) -> tar::Header {
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(kind);
+    header.set_size(size);
+    header.set_mode(mode);
+    header.set_mtime(ARCHIVE_MTIME);
+    header
+}
+
+fn append_tar<W: Write>(
+    tar: &mut tar::Builder<W>,
+    name: &str,
+    size: u64,
+    data: impl io::Read,
+) -> io::Result<()> {
+    let mut header = tar_header(tar::EntryType::Regular, size, 0o644);
+    tar.append_data(&mut header, name, data)
+}
+
+// Archive entry names always use `/`, whatever the host separator.
+fn archive_name(rel: &Path) -> Result<String> {
+    let parts = rel
+        .components()
+        .map(|c| match c {
+            Component::Normal(part) => Ok(part.to_string_lossy().into_owned()),
+            _ => Err(Error::OutputOutsideRoot {
+                path: rel.to_path_buf(),
+            }),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(parts.join("/"))
+}
+
+impl OutputSink for ArchiveSink {
+    fn create_dir(&mut self, rel: &Path) -> Result<()> {
+        if !self.entries.insert(rel.to_path_buf()) {
+            return Ok(());
+        }
+        let name = archive_name(rel)? + "/";
+        let written = self.writer().and_then(|writer| match writer {
+            ArchiveWriter::Zip(zip) => zip
+                .add_directory(name, zip::write::SimpleFileOptions::default())
+                .map_err(io::Error::from),
+            ArchiveWriter::Tar(tar) => {
+                let mut header = tar_header(tar::EntryType::Directory, 0, 0o755);
+                tar.append_data(&mut header, name, io::empty())
+            }
+            ArchiveWriter::TarGz(tar) => {
+                let mut header = tar_header(tar::EntryType::Directory, 0, 0o755);
+                tar.append_data(&mut header, name, io::empty())
+            }
+        });
+        written.map_err(Error::io(&self.partial))
+    }
+
+    fn write_file(&mut self, rel: &Path, contents: &[u8]) -> Result<()> {
+        self.append(rel, contents.len() as u64, contents)
+    }
+
+    fn copy_file(&mut self, rel: &Path, src: &Path) -> Result<()> {
+        let file = File::open(src).map_err(Error::io(src))?;
+        let size = file.metadata().map_err(Error::io(src))?.len();
+        self.append(rel, size, file)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        let finished = match self.writer.take() {
+            Some(ArchiveWriter::Zip(zip)) => zip
+                .finish()
+                .map_err(io::Error::from)
+                .and_then(|mut out| out.flush()),
+            Some(ArchiveWriter::Tar(tar)) => tar.into_inner().and_then(|mut out| out.flush()),
+            Some(ArchiveWriter::TarGz(tar)) => tar
+                .into_inner()
+                .and_then(|gz| gz.finish())
+                .and_then(|mut out| out.flush()),
+            None => return Ok(()),
+        };
+        finished.map_err(Error::io(&self.partial))?;
+        fs::rename(&self.partial, &self.path).map_err(Error::io(&self.path))
+    }
+}
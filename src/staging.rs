@@ -0,0 +1,344 @@
+//
+// staging.rs
+// BigDiff-rs
+//
+// Crash-safe directory output: results are written to a staging folder inside the output root, journaled per entry, and moved into place only when the run completes.
+//
+// Thales Matheus Mendonça Santos - November 2025
+//
+// Staging, journaling and completion marker for directory outputs, plus `--resume` support.
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use walkdir::WalkDir;
+
+use crate::cli::{OnExisting, Options};
+use crate::error::{Error, Result};
+use crate::manifest::{clean_outputs, EntryKind, Manifest, ManifestEntry};
+use crate::sink::{OutputSink, Preserve};
+use crate::utils::{ensure_output_target_safe, sha256_bytes, sha256_file};
+
+/// Folder inside the output root that receives outputs until the run completes.
+pub const STAGING_DIR: &str = ".bigdiff-staging";
+/// Journal of finished entries, kept inside the staging folder.
+pub const JOURNAL_FILE: &str = ".bigdiff-journal";
+/// Written at the output root once every output is in place.
+pub const COMPLETE_MARKER: &str = ".bigdiff-complete";
+
+#[derive(Serialize, Deserialize)]
+/// One journal line: an entry whose outputs are fully written, as staged paths.
+struct JournalEntry {
+    rel: PathBuf,
+    outputs: Vec<ManifestEntry>,
+}
+
+/// An in-progress directory output.
+pub struct Staging {
+    out_root: PathBuf,
+    dir: PathBuf,
+    journal: File,
+    done: HashSet<PathBuf>,
+    // Manifest details of staged outputs, by staged path.
+    described: HashMap<PathBuf, ManifestEntry>,
+}
+
+impl Staging {
+    /// Prepares the staging folder under `out_root`.
+    ///
+    /// A fresh run discards any leftover staging folder. With `resume`, entries recorded in the
+    /// journal are kept and every other staged file (partial outputs) is removed.
+    pub fn open(out_root: &Path, resume: bool) -> Result<Self> {
+        let dir = out_root.join(STAGING_DIR);
+        ensure_output_target_safe(out_root, &dir)?;
+
+        // Whatever happens next, the output is no longer a completed run.
+        let marker = out_root.join(COMPLETE_MARKER);
+        ensure_output_target_safe(out_root, &marker)?;
+        if marker.exists() {
+            fs::remove_file(&marker).map_err(Error::io(&marker))?;
+        }
+
+        let journal_path = dir.join(JOURNAL_FILE);
+        let mut done = HashSet::new();
+        let mut described = HashMap::new();
+        if resume && journal_path.is_file() {
+            let mut keep = HashSet::new();
+            let file = File::open(&journal_path).map_err(Error::io(&journal_path))?;
+            for line in BufReader::new(file).lines() {
+                let line = line.map_err(Error::io(&journal_path))?;
+                // A torn last line means that entry did not finish.
+                let Ok(entry) = serde_json::from_str::<JournalEntry>(&line) else {
+                    continue;
+                };
+                for output in entry.outputs {
+                    keep.insert(output.path.clone());
+                    described.insert(output.path.clone(), output);
+                }
+                done.insert(entry.rel);
+            }
+            remove_unjournaled(&dir, &keep)?;
+        } else if dir.exists() {
+            fs::remove_dir_all(&dir).map_err(Error::io(&dir))?;
+        }
+
+        fs::create_dir_all(&dir).map_err(Error::io(&dir))?;
+        let journal = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&journal_path)
+            .map_err(Error::io(&journal_path))?;
+        Ok(Staging {
+            out_root: out_root.to_path_buf(),
+            dir,
+            journal,
+            done,
+            described,
+        })
+    }
+
+    /// Whether `out_root` holds the journal of an interrupted run that `--resume` can continue.
+    pub fn can_resume(out_root: &Path) -> bool {
+        out_root.join(STAGING_DIR).join(JOURNAL_FILE).is_file()
+    }
+
+    /// Folder the run writes into.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Whether a previous attempt already wrote every output for `rel`.
+    pub fn is_done(&self, rel: &Path) -> bool {
+        self.done.contains(rel)
+    }
+
+    /// Journals `rel` as finished once its `outputs` are written.
+    pub fn record(&mut self, rel: &Path, outputs: Vec<ManifestEntry>) -> Result<()> {
+        let entry = JournalEntry {
+            rel: rel.to_path_buf(),
+            outputs,
+        };
+        let line = serde_json::to_string(&entry).map_err(|e| Error::Render {
+            message: e.to_string(),
+        })? + "\n";
+        let journal_path = self.dir.join(JOURNAL_FILE);
+        self.journal
+            .write_all(line.as_bytes())
+            .and_then(|_| self.journal.flush())
+            .map_err(Error::io(journal_path))?;
+        self.done.insert(entry.rel);
+        self.describe(entry.outputs);
+        Ok(())
+    }
+
+    /// Keeps manifest details for staged outputs that are not journaled (e.g. ERRORS files).
+    pub fn describe(&mut self, outputs: Vec<ManifestEntry>) {
+        for output in outputs {
+            self.described.insert(output.path.clone(), output);
+        }
+    }
+
+    /// Moves every staged output into the output root, then writes the manifest and the
+    /// completion marker. With [`OnExisting::Merge`], a staged file that would replace an
+    /// existing one fails the run before anything is moved.
+    ///
+    /// The journal goes last, so a failure part-way still leaves a run `--resume` can finish.
+    pub fn finish(mut self, opts: &Options) -> Result<()> {
+        drop(self.journal);
+        let previous = Manifest::load(&self.out_root)?.unwrap_or_default();
+
+        // Collect first so renaming does not disturb the directory walk.
+        let entries = WalkDir::new(&self.dir)
+            .min_depth(1)
+            .sort_by_file_name()
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| {
+                let path = e.path().unwrap_or(&self.dir).to_path_buf();
+                Error::io(path)(e.into())
+            })?;
+        if opts.on_existing == OnExisting::Merge {
+            for entry in entries.iter().filter(|entry| !entry.file_type().is_dir()) {
+                let Ok(rel) = entry.path().strip_prefix(&self.dir) else {
+                    continue;
+                };
+                let dst = self.out_root.join(rel);
+                if rel != Path::new(JOURNAL_FILE) && fs::symlink_metadata(&dst).is_ok() {
+                    return Err(Error::OutputClash { path: dst });
+                }
+            }
+        }
+        let mut outputs = Vec::new();
+        for entry in entries {
+            let Ok(rel) = entry.path().strip_prefix(&self.dir) else {
+                continue;
+            };
+            if rel == Path::new(JOURNAL_FILE) {
+                continue;
+            }
+            let mut output = match self.described.remove(rel) {
+                Some(output) => output,
+                None if entry.file_type().is_dir() => ManifestEntry::dir(rel.to_path_buf()),
+                None => {
+                    let size = entry
+                        .metadata()
+                        .map_err(|e| Error::io(entry.path())(e.into()))?;
+                    ManifestEntry::file(rel.to_path_buf(), sha256_file(entry.path())?, size.len())
+                }
+            };
+            let dst = self.out_root.join(rel);
+            ensure_output_target_safe(&self.out_root, &dst)?;
+            if output.kind == EntryKind::Dir {
+                fs::create_dir_all(&dst).map_err(Error::io(&dst))?;
+            } else {
+                // Same filesystem, so each file lands in one step.
+                fs::rename(entry.path(), &dst).map_err(Error::io(&dst))?;
+            }
+            if let Ok(path) = dst.strip_prefix(&self.out_root) {
+                output.path = path.to_path_buf();
+                outputs.push(output);
+            }
+        }
+        // Journaled outputs an earlier, interrupted `finish` already moved into place.
+        let mut moved: Vec<_> = self
+            .described
+            .into_values()
+            .filter(|output| self.out_root.join(&output.path).exists())
+            .collect();
+        moved.sort_by(|a, b| a.path.cmp(&b.path));
+        outputs.extend(moved);
+
+        // Earlier outputs that are still around stay listed, so a later clean removes them too.
+        let mut listed: Vec<_> = previous
+            .outputs
+            .into_iter()
+            .filter(|old| {
+                self.out_root.join(&old.path).exists()
+                    && !outputs.iter().any(|new| new.path == old.path)
+            })
+            .collect();
+        listed.extend(outputs);
+        Manifest::new(opts, listed).save(&self.out_root)?;
+
+        let marker = self.out_root.join(COMPLETE_MARKER);
+        let content = format!("BigDiff {} run completed\n", env!("CARGO_PKG_VERSION"));
+        fs::write(&marker, content).map_err(Error::io(&marker))?;
+        fs::remove_dir_all(&self.dir).map_err(Error::io(&self.dir))
+    }
+}
+
+/// Applies the `--on-existing` policy to a directory output before a fresh run.
+pub fn prepare_output_dir(out_root: &Path, policy: OnExisting) -> Result<()> {
+    let is_empty = match fs::read_dir(out_root) {
+        Ok(mut entries) => entries.next().is_none(),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => true,
+        Err(err) => return Err(Error::io(out_root)(err)),
+    };
+    if is_empty {
+        return Ok(());
+    }
+    match policy {
+        OnExisting::Error => Err(Error::OutputExists {
+            path: out_root.to_path_buf(),
+        }),
+        OnExisting::Clean => match Manifest::load(out_root)? {
+            Some(manifest) => clean_outputs(out_root, &manifest),
+            None => Err(Error::UnknownOutputs {
+                path: out_root.to_path_buf(),
+            }),
+        },
+        OnExisting::Merge | OnExisting::Overwrite => Ok(()),
+    }
+}
+
+// Drops staged files that no journal entry accounts for (outputs of an interrupted entry).
+fn remove_unjournaled(dir: &Path, keep: &HashSet<PathBuf>) -> Result<()> {
+    for entry in WalkDir::new(dir).min_depth(1).into_iter().flatten() {
+        if entry.file_type().is_dir() {
+            continue;
+        }
+        let Ok(rel) = entry.path().strip_prefix(dir) else {
+            continue;
+        };
+        if rel != Path::new(JOURNAL_FILE) && !keep.contains(rel) {
+            fs::remove_file(entry.path()).map_err(Error::io(entry.path()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Forwards to another sink and remembers what was written, with hashes and sizes.
+pub(crate) struct RecordingSink<'a> {
+    pub inner: &'a mut dyn OutputSink,
+    pub written: Vec<ManifestEntry>,
+    /// Hex SHA-256 of source files by path, so a copied file is hashed at most once.
+    pub hashes: HashMap<PathBuf, String>,
+}
+
+impl<'a> RecordingSink<'a> {
+    /// An empty recording over `inner`; `hashes` are source digests the caller already has.
+    pub fn new(inner: &'a mut dyn OutputSink, hashes: HashMap<PathBuf, String>) -> Self {
+        RecordingSink {
+            inner,
+            written: Vec::new(),
+            hashes,
+        }
+    }
+}
+
+impl OutputSink for RecordingSink<'_> {
+    fn create_dir(&mut self, rel: &Path) -> Result<()> {
+        self.inner.create_dir(rel)?;
+        self.written.push(ManifestEntry::dir(rel.to_path_buf()));
+        Ok(())
+    }
+
+    fn write_file(&mut self, rel: &Path, contents: &[u8]) -> Result<()> {
+        self.inner.write_file(rel, contents)?;
+        self.written.push(ManifestEntry::file(
+            rel.to_path_buf(),
+            sha256_bytes(contents),
+            contents.len() as u64,
+        ));
+        Ok(())
+    }
+
+    fn copy_file(&mut self, rel: &Path, src: &Path) -> Result<()> {
+        self.inner.copy_file(rel, src)?;
+        let size = fs::metadata(src).map_err(Error::io(src))?.len();
+        let sha256 = match self.hashes.get(src) {
+            Some(sha256) => sha256.clone(),
+            None => {
+                let sha256 = sha256_file(src)?;
+                self.hashes.insert(src.to_path_buf(), sha256.clone());
+                sha256
+            }
+        };
+        self.written
+            .push(ManifestEntry::file(rel.to_path_buf(), sha256, size));
+        Ok(())
+    }
+
+    fn copy_metadata(&mut self, rel: &Path, src: &Path, preserve: Preserve) -> Result<()> {
+        self.inner.copy_metadata(rel, src, preserve)
+    }
+}
+
+/// Writes nothing; replays entries finished before a resume so they are still counted.
+pub(crate) struct ReplaySink;
+
+impl OutputSink for ReplaySink {
+    fn create_dir(&mut self, _rel: &Path) -> Result<()> {
+        Ok(())
+    }
+
+    fn write_file(&mut self, _rel: &Path, _contents: &[u8]) -> Result<()> {
+        Ok(())
+    }
+
+    fn copy_file(&mut self, _rel: &Path, _src: &Path) -> Result<()> {
+        Ok(())
+    }
+}
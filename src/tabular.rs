@@ -0,0 +1,246 @@
+//
+// tabular.rs
+// BigDiff-rs
+//
+// Keyed row-level comparison for CSV/TSV files: matches rows by user-chosen key columns and reports added/removed rows and per-cell changes.
+//
+// Thales Matheus Mendonça Santos - November 2025
+//
+// Tabular diff so re-sorted exports and reordered columns are not reported as whole-file rewrites.
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::error::{Error, Result};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// One difference between two keyed tables.
+pub enum RowChange {
+    ColumnAdded {
+        column: String,
+    },
+    ColumnRemoved {
+        column: String,
+    },
+    /// A row only in the target; `values` pairs each non-key column with its cell.
+    RowAdded {
+        key: Vec<String>,
+        values: Vec<(String, String)>,
+    },
+    /// A row only in the base; `values` pairs each non-key column with its cell.
+    RowRemoved {
+        key: Vec<String>,
+        values: Vec<(String, String)>,
+    },
+    CellChanged {
+        key: Vec<String>,
+        column: String,
+        old: String,
+        new: String,
+    },
+}
+
+/// Field delimiter for tabular extensions, or `None` for other files.
+pub fn delimiter_for(path: &Path) -> Option<u8> {
+    let ext = path.extension()?.to_str()?.to_lowercase();
+    match ext.as_str() {
+        "csv" => Some(b','),
+        "tsv" => Some(b'\t'),
+        "psv" => Some(b'|'),
+        _ => None,
+    }
+}
+
+struct Table {
+    headers: Vec<String>,
+    rows: BTreeMap<Vec<String>, Vec<String>>,
+}
+
+impl Table {
+    /// Non-key cells of `row` with their column names, in header order.
+    fn values(&self, row: &[String], keys: &[String]) -> Vec<(String, String)> {
+        self.headers
+            .iter()
+            .enumerate()
+            .filter(|(_, name)| !keys.contains(name))
+            .map(|(i, name)| (name.clone(), row.get(i).cloned().unwrap_or_default()))
+            .collect()
+    }
+}
+
+fn parse_table(text: &str, delimiter: u8, keys: &[String]) -> Result<Table> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .from_reader(text.as_bytes());
+    let headers: Vec<String> = reader
+        .headers()
+        .map_err(table_error)?
+        .iter()
+        .map(str::to_string)
+        .collect();
+
+    let key_idx = keys
+        .iter()
+        .map(|k| match headers.iter().position(|h| h == k) {
+            Some(i) => Ok(i),
+            None => Err(Error::Table {
+                message: format!("Key column {k:?} not found in header"),
+            }),
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut rows = BTreeMap::new();
+    for record in reader.records() {
+        let record: Vec<String> = record
+            .map_err(table_error)?
+            .iter()
+            .map(str::to_string)
+            .collect();
+        let key: Vec<String> = key_idx
+            .iter()
+            .map(|&i| record.get(i).cloned().unwrap_or_default())
+            .collect();
+        if rows.insert(key.clone(), record).is_some() {
+            return Err(Error::Table {
+                message: format!("Duplicate key {key:?}"),
+            });
+        }
+    }
+    Ok(Table { headers, rows })
+}
+
+/// Compares two tables row by row, matching rows on `keys` and cells on header names.
+///
+/// Fails when a key column is missing or a key is duplicated; callers fall back to a line diff.
+pub fn tabular_diff(
+    a_text: &str,
+    b_text: &str,
+    delimiter: u8,
+    keys: &[String],
+) -> Result<Vec<RowChange>> {
+    let a = parse_table(a_text, delimiter, keys)?;
+    let b = parse_table(b_text, delimiter, keys)?;
+    let mut changes = Vec::new();
+
+    for column in &b.headers {
+        if !a.headers.contains(column) {
+            changes.push(RowChange::ColumnAdded {
+                column: column.clone(),
+            });
+        }
+    }
+    for column in &a.headers {
+        if !b.headers.contains(column) {
+            changes.push(RowChange::ColumnRemoved {
+                column: column.clone(),
+            });
+        }
+    }
+
+    // Cells are compared only for columns present on both sides, whatever their position.
+    let shared: Vec<(&String, usize, usize)> = a
+        .headers
+        .iter()
+        .enumerate()
+        .filter_map(|(ai, name)| {
+            let bi = b.headers.iter().position(|h| h == name)?;
+            Some((name, ai, bi))
+        })
+        .filter(|(name, _, _)| !keys.contains(name))
+        .collect();
+
+    for (key, a_row) in &a.rows {
+        let Some(b_row) = b.rows.get(key) else {
+            changes.push(RowChange::RowRemoved {
+                key: key.clone(),
+                values: a.values(a_row, keys),
+            });
+            continue;
+        };
+        for (column, ai, bi) in &shared {
+            let old = a_row.get(*ai).map(String::as_str).unwrap_or("");
+            let new = b_row.get(*bi).map(String::as_str).unwrap_or("");
+            if old != new {
+                changes.push(RowChange::CellChanged {
+                    key: key.clone(),
+                    column: (*column).clone(),
+                    old: old.to_string(),
+                    new: new.to_string(),
+                });
+            }
+        }
+    }
+    for (key, b_row) in &b.rows {
+        if !a.rows.contains_key(key) {
+            changes.push(RowChange::RowAdded {
+                key: key.clone(),
+                values: b.values(b_row, keys),
+            });
+        }
+    }
+    Ok(changes)
+}
+
+/// Renders changes as a CSV report: `change,<key columns...>,column,old,new`.
+///
+/// Added and removed rows get one line per non-key column, with the cell under `new` or `old`.
+pub fn render_report(changes: &[RowChange], keys: &[String]) -> Result<String> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    let mut header = vec!["change".to_string()];
+    header.extend(keys.iter().cloned());
+    header.extend(["column", "old", "new"].map(String::from));
+    writer.write_record(&header).map_err(render_error)?;
+
+    let blank_key = vec![String::new(); keys.len()];
+    for change in changes {
+        let lines: Vec<(&str, &[String], &str, &str, &str)> = match change {
+            RowChange::ColumnAdded { column } => {
+                vec![("column_added", &blank_key, column, "", "")]
+            }
+            RowChange::ColumnRemoved { column } => {
+                vec![("column_removed", &blank_key, column, "", "")]
+            }
+            RowChange::RowAdded { key, values } => cells(values)
+                .map(|(column, value)| ("row_added", key.as_slice(), column, "", value))
+                .collect(),
+            RowChange::RowRemoved { key, values } => cells(values)
+                .map(|(column, value)| ("row_removed", key.as_slice(), column, value, ""))
+                .collect(),
+            RowChange::CellChanged {
+                key,
+                column,
+                old,
+                new,
+            } => vec![("cell_changed", key, column, old, new)],
+        };
+        for (kind, key, column, old, new) in lines {
+            let mut record = vec![kind];
+            record.extend(key.iter().map(String::as_str));
+            record.extend([column, old, new]);
+            writer.write_record(&record).map_err(render_error)?;
+        }
+    }
+    let bytes = writer.into_inner().map_err(render_error)?;
+    String::from_utf8(bytes).map_err(render_error)
+}
+
+// Each `(column, value)` of a row; a row with only key columns still gets one line.
+fn cells(values: &[(String, String)]) -> impl Iterator<Item = (&str, &str)> {
+    let bare = values.is_empty().then_some(("", ""));
+    values
+        .iter()
+        .map(|(column, value)| (column.as_str(), value.as_str()))
+        .chain(bare)
+}
+
+fn table_error(err: csv::Error) -> Error {
+    Error::Table {
+        message: err.to_string(),
+    }
+}
+
+fn render_error(err: impl std::fmt::Display) -> Error {
+    Error::Render {
+        message: err.to_string(),
+    }
+}
@@ -2,19 +2,21 @@
 // utils.rs
 // BigDiff-rs
 //
-// Shared helpers for parsing sizes, detecting binary files, reading text robustly, avoiding filename collisions, and mirroring deleted paths.
+// Shared helpers for parsing sizes, reading text robustly, and mirroring deleted paths.
 //
 // Thales Matheus Mendonça Santos - November 2025
 //
 // Shared helpers used across CLI and diff logic.
 use std::fs::{self, File};
-use std::io::{self, Read};
-use std::path::{Path, PathBuf};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
 
-use anyhow::{bail, Context, Result};
-use encoding_rs::WINDOWS_1252;
+use encoding_rs::Encoding;
 use sha2::{Digest, Sha256};
 
+use crate::encoding::TextEncoding;
+use crate::error::{Error, Result};
+
 /// Parses a human-friendly size string like "5MB" or "2gib" into bytes.
 pub fn parse_size(s: &str) -> u64 {
     let s = s.trim().to_lowercase();
@@ -41,83 +43,69 @@ pub fn parse_size(s: &str) -> u64 {
     s.parse().unwrap_or(0)
 }
 
-/// Cheap heuristic: look for null bytes or invalid UTF-8 to flag likely binary files.
-pub fn is_probably_binary(path: &Path) -> bool {
-    let mut file = match File::open(path) {
-        Ok(f) => f,
-        Err(_) => return true,
-    };
-    let mut buffer = [0u8; 4096];
-    let n = match file.read(&mut buffer) {
-        Ok(n) => n,
-        Err(_) => return true,
-    };
-    if n == 0 {
-        return false;
-    }
-
-    let slice = &buffer[..n];
-    if slice.contains(&0) {
-        return true;
-    }
-    std::str::from_utf8(slice).is_err()
+/// Reads text in its detected encoding (BOM, BOM-less UTF-16, UTF-8, then Windows-1252),
+/// optionally normalizing EOLs. No encoding can be forced; use [`read_text`] for that.
+pub fn read_text_best_effort(path: &Path, normalize_eol: bool) -> Result<String> {
+    Ok(read_text(path, None, normalize_eol)?.0)
 }
 
-/// Reads text using UTF-8 first, then Windows-1252, optionally normalizing EOLs.
-pub fn read_text_best_effort(path: &Path, normalize_eol: bool) -> Result<String> {
-    let bytes = fs::read(path)?;
-    let content = match String::from_utf8(bytes.clone()) {
-        Ok(s) => s,
-        Err(_) => {
-            // Fallback to a permissive single-byte encoding to salvage imperfect files.
-            let (res, _, _) = WINDOWS_1252.decode(&bytes);
-            res.into_owned()
-        }
-    };
+/// Reads text in its detected encoding (see [`TextEncoding::detect`]) and reports which one it was.
+pub fn read_text(
+    path: &Path,
+    forced: Option<&'static Encoding>,
+    normalize_eol: bool,
+) -> Result<(String, TextEncoding)> {
+    let bytes = fs::read(path).map_err(Error::io(path))?;
+    let encoding = TextEncoding::detect(&bytes, forced);
+    let content = encoding.decode(&bytes);
 
     if normalize_eol {
-        Ok(content.replace("\r\n", "\n").replace('\r', "\n"))
+        Ok((content.replace("\r\n", "\n").replace('\r', "\n"), encoding))
     } else {
-        Ok(content)
+        Ok((content, encoding))
     }
 }
 
 /// Compares two files by streaming SHA-256 hashes to avoid loading everything at once.
-pub fn file_bytes_equal(p1: &Path, p2: &Path) -> bool {
-    let hash_file = |p: &Path| -> Option<String> {
-        let mut file = File::open(p).ok()?;
-        let mut hasher = Sha256::new();
-        io::copy(&mut file, &mut hasher).ok()?;
-        Some(hex::encode(hasher.finalize()))
-    };
+///
+/// Unreadable files are an error rather than a difference.
+pub fn file_bytes_equal(p1: &Path, p2: &Path) -> Result<bool> {
+    Ok(sha256_file(p1)? == sha256_file(p2)?)
+}
+
+/// Reads up to `sample` bytes from the start of a file and, when it is longer, up to `sample`
+/// bytes from its end; the tail never overlaps the head. `None` if the file cannot be read.
+pub fn read_head_and_tail(path: &Path, sample: u64) -> Option<(Vec<u8>, Option<Vec<u8>>)> {
+    let mut file = File::open(path).ok()?;
+    let len = file.metadata().ok()?.len();
 
-    match (hash_file(p1), hash_file(p2)) {
-        (Some(h1), Some(h2)) => h1 == h2,
-        _ => false,
+    let mut head = Vec::new();
+    (&mut file).take(sample).read_to_end(&mut head).ok()?;
+    if len <= sample {
+        return Some((head, None));
     }
+
+    let start = (len - sample).max(sample);
+    let mut tail = Vec::new();
+    file.seek(SeekFrom::Start(start)).ok()?;
+    file.take(sample).read_to_end(&mut tail).ok()?;
+    Some((head, Some(tail)))
 }
 
-/// If a path already exists, append " (n)" before the extension to avoid overwrite.
-pub fn avoid_collision(path: &Path) -> PathBuf {
-    if !path.exists() {
-        return path.to_path_buf();
-    }
-    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("");
-    let ext = path
-        .extension()
-        .and_then(|s| s.to_str())
-        .map(|e| format!(".{e}"))
-        .unwrap_or_default();
-    let parent = path.parent().unwrap_or(Path::new("."));
-
-    let mut n = 1;
-    loop {
-        let candidate = parent.join(format!("{stem} ({n}){ext}"));
-        if !candidate.exists() {
-            return candidate;
-        }
-        n += 1;
-    }
+/// Hex-encoded SHA-256 of an in-memory buffer.
+pub fn sha256_bytes(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
+
+/// Hex-encoded SHA-256 of a file's contents.
+pub fn sha256_file(path: &Path) -> Result<String> {
+    let hash = || -> io::Result<String> {
+        let mut file = File::open(path)?;
+        let mut hasher = Sha256::new();
+        io::copy(&mut file, &mut hasher)?;
+        Ok(hex::encode(hasher.finalize()))
+    };
+    hash().map_err(Error::io(path))
 }
 
 /// Refuses writes when any existing output path component is a symlink.
@@ -132,55 +120,38 @@ pub fn ensure_output_target_safe(out_root: &Path, target: &Path) -> Result<()> {
         match fs::symlink_metadata(ancestor) {
             Ok(meta) if meta.file_type().is_symlink() => {
                 if ancestor == out_root {
-                    bail!(
-                        "Refusing to write into symlinked output root: {:?}",
-                        out_root
-                    );
+                    return Err(Error::SymlinkedOutputRoot {
+                        path: out_root.to_path_buf(),
+                    });
                 }
-                bail!(
-                    "Refusing to write through symlinked output path component: {:?}",
-                    ancestor
-                );
+                return Err(Error::SymlinkedOutputComponent {
+                    path: ancestor.to_path_buf(),
+                });
             }
             Ok(_) => {}
             Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
-            Err(err) => return Err(err.into()),
+            Err(err) => return Err(Error::io(ancestor)(err)),
         }
     }
 
     let rel = target
         .strip_prefix(out_root)
-        .with_context(|| format!("Output target escapes output root: {target:?}"))?;
+        .map_err(|_| Error::OutputOutsideRoot {
+            path: target.to_path_buf(),
+        })?;
 
     let mut current = out_root.to_path_buf();
     for comp in rel.components() {
         current.push(comp);
         match fs::symlink_metadata(&current) {
             Ok(meta) if meta.file_type().is_symlink() => {
-                bail!(
-                    "Refusing to write through symlinked output path component: {:?}",
-                    current
-                );
+                return Err(Error::SymlinkedOutputComponent { path: current });
             }
             Ok(_) => {}
             Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
-            Err(err) => return Err(err.into()),
+            Err(err) => return Err(Error::io(&current)(err)),
         }
     }
 
     Ok(())
 }
-
-/// Adds `.deleted` after every path component to mirror removed folder hierarchies.
-pub fn rel_parts_with_deleted_suffix(rel: &Path) -> PathBuf {
-    let mut new_path = PathBuf::new();
-    for comp in rel.components() {
-        if let std::path::Component::Normal(name) = comp {
-            let s = name.to_string_lossy();
-            new_path.push(format!("{s}.deleted"));
-        } else {
-            new_path.push(comp);
-        }
-    }
-    new_path
-}
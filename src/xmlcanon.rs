@@ -0,0 +1,251 @@
+//
+// xmlcanon.rs
+// BigDiff-rs
+//
+// Canonicalizes XML documents (sorted attributes, normalized text whitespace, namespace URIs instead of prefixes) and reports element-level changes with XPath-like locations.
+//
+// Thales Matheus Mendonça Santos - November 2025
+//
+// Canonical XML comparison so tool-regenerated documents only differ when their content does.
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+use roxmltree::{Document, Node, ParsingOptions};
+
+const XML_EXTS: &[&str] = &[
+    "xml", "svg", "xhtml", "xsd", "xsl", "xslt", "plist", "html", "htm",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// An element after canonicalization; names carry the namespace URI rather than the prefix.
+struct Element {
+    name: String,
+    attrs: BTreeMap<String, String>,
+    text: String,
+    children: Vec<Element>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// One difference between two canonicalized documents, located by an XPath-like path.
+pub enum XmlChange {
+    ElementAdded {
+        path: String,
+    },
+    ElementRemoved {
+        path: String,
+    },
+    AttrAdded {
+        path: String,
+        value: String,
+    },
+    AttrRemoved {
+        path: String,
+        value: String,
+    },
+    AttrChanged {
+        path: String,
+        old: String,
+        new: String,
+    },
+    TextChanged {
+        path: String,
+        old: String,
+        new: String,
+    },
+    Reordered {
+        path: String,
+    },
+}
+
+impl fmt::Display for XmlChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            XmlChange::ElementAdded { path } => write!(f, "{path}: element added"),
+            XmlChange::ElementRemoved { path } => write!(f, "{path}: element removed"),
+            XmlChange::AttrAdded { path, value } => write!(f, "{path}: added {value:?}"),
+            XmlChange::AttrRemoved { path, value } => write!(f, "{path}: removed {value:?}"),
+            XmlChange::AttrChanged { path, old, new }
+            | XmlChange::TextChanged { path, old, new } => {
+                write!(f, "{path}: {old:?} -> {new:?}")
+            }
+            XmlChange::Reordered { path } => write!(f, "{path}: children reordered"),
+        }
+    }
+}
+
+/// Whether the path looks like an XML document BigDiff can canonicalize.
+pub fn is_xml_path(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| XML_EXTS.contains(&e.to_lowercase().as_str()))
+}
+
+/// Canonicalizes both documents and lists their differences.
+///
+/// Returns `None` for non-XML paths or when either side is not well-formed (e.g. most HTML),
+/// so the caller can fall back to a line diff.
+pub fn canonical_xml_diff(rel: &Path, a_text: &str, b_text: &str) -> Option<Vec<XmlChange>> {
+    if !is_xml_path(rel) {
+        return None;
+    }
+    let a = canonicalize(a_text)?;
+    let b = canonicalize(b_text)?;
+
+    let mut changes = Vec::new();
+    if a.name != b.name {
+        changes.push(XmlChange::ElementRemoved {
+            path: format!("/{}", local_name(&a.name)),
+        });
+        changes.push(XmlChange::ElementAdded {
+            path: format!("/{}", local_name(&b.name)),
+        });
+    } else {
+        compare_elements(&format!("/{}", local_name(&a.name)), &a, &b, &mut changes);
+    }
+    Some(changes)
+}
+
+fn canonicalize(text: &str) -> Option<Element> {
+    let opts = ParsingOptions {
+        allow_dtd: true,
+        ..ParsingOptions::default()
+    };
+    let doc = Document::parse_with_options(text, opts).ok()?;
+    Some(convert(doc.root_element(), false))
+}
+
+fn qualified(namespace: Option<&str>, local: &str) -> String {
+    match namespace {
+        Some(uri) => format!("{{{uri}}}{local}"),
+        None => local.to_string(),
+    }
+}
+
+// Locations show local names; the namespace URI only matters for matching.
+fn local_name(qualified: &str) -> &str {
+    match qualified.strip_prefix('{') {
+        Some(rest) => rest.split_once('}').map_or(qualified, |(_, local)| local),
+        None => qualified,
+    }
+}
+
+fn collapse_whitespace(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Namespace of the `xml:` prefix, which needs no declaration.
+const XML_NAMESPACE: &str = "http://www.w3.org/XML/1998/namespace";
+
+// Comments and processing instructions are dropped. Text is whitespace-normalized and
+// whitespace-only text between elements disappears, unless `xml:space="preserve"` applies;
+// attribute values are compared as the parser normalized them.
+fn convert(node: Node, inherited_preserve: bool) -> Element {
+    let tag = node.tag_name();
+    let preserve = match node.attribute((XML_NAMESPACE, "space")) {
+        Some("preserve") => true,
+        Some("default") => false,
+        _ => inherited_preserve,
+    };
+    let attrs = node
+        .attributes()
+        .map(|a| (qualified(a.namespace(), a.name()), a.value().to_string()))
+        .collect();
+
+    let mut text = String::new();
+    let mut children = Vec::new();
+    for child in node.children() {
+        if child.is_element() {
+            children.push(convert(child, preserve));
+        } else if child.is_text() {
+            text.push_str(child.text().unwrap_or(""));
+            if !preserve {
+                text.push(' ');
+            }
+        }
+    }
+
+    Element {
+        name: qualified(tag.namespace(), tag.name()),
+        attrs,
+        text: if preserve {
+            text
+        } else {
+            collapse_whitespace(&text)
+        },
+        children,
+    }
+}
+
+fn compare_elements(path: &str, a: &Element, b: &Element, changes: &mut Vec<XmlChange>) {
+    let before = changes.len();
+
+    for (name, old) in &a.attrs {
+        let attr_path = format!("{path}/@{}", local_name(name));
+        match b.attrs.get(name) {
+            Some(new) if new != old => changes.push(XmlChange::AttrChanged {
+                path: attr_path,
+                old: old.clone(),
+                new: new.clone(),
+            }),
+            Some(_) => {}
+            None => changes.push(XmlChange::AttrRemoved {
+                path: attr_path,
+                value: old.clone(),
+            }),
+        }
+    }
+    for (name, value) in &b.attrs {
+        if !a.attrs.contains_key(name) {
+            changes.push(XmlChange::AttrAdded {
+                path: format!("{path}/@{}", local_name(name)),
+                value: value.clone(),
+            });
+        }
+    }
+
+    if a.text != b.text {
+        changes.push(XmlChange::TextChanged {
+            path: format!("{path}/text()"),
+            old: a.text.clone(),
+            new: b.text.clone(),
+        });
+    }
+
+    // Pair children by name and position among same-named siblings, like XPath `name[n]`.
+    let mut names: Vec<&str> = Vec::new();
+    for child in a.children.iter().chain(&b.children) {
+        if !names.contains(&child.name.as_str()) {
+            names.push(&child.name);
+        }
+    }
+    for name in names {
+        let a_group: Vec<&Element> = a.children.iter().filter(|c| c.name == name).collect();
+        let b_group: Vec<&Element> = b.children.iter().filter(|c| c.name == name).collect();
+        let indexed = a_group.len().max(b_group.len()) > 1;
+        let name = local_name(name);
+
+        for i in 0..a_group.len().max(b_group.len()) {
+            let child_path = if indexed {
+                format!("{path}/{name}[{}]", i + 1)
+            } else {
+                format!("{path}/{name}")
+            };
+            match (a_group.get(i), b_group.get(i)) {
+                (Some(a_child), Some(b_child)) => {
+                    compare_elements(&child_path, a_child, b_child, changes)
+                }
+                (Some(_), None) => changes.push(XmlChange::ElementRemoved { path: child_path }),
+                (None, Some(_)) => changes.push(XmlChange::ElementAdded { path: child_path }),
+                (None, None) => {}
+            }
+        }
+    }
+
+    // Same children, different interleaving of names.
+    if changes.len() == before && a != b {
+        changes.push(XmlChange::Reordered {
+            path: path.to_string(),
+        });
+    }
+}
@@ -0,0 +1,286 @@
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use bigdiff::{
+    run_bigdiff, visit_diff, ArchiveLimits, DiffEvent, Error, InputTree, Options, OutputFormat,
+};
+
+mod common;
+use common::unique_temp_dir;
+
+fn write_zip(path: &Path, files: &[(&str, &str)]) {
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    let mut zip = zip::ZipWriter::new(File::create(path).unwrap());
+    for (name, content) in files {
+        zip.start_file(*name, zip::write::SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(content.as_bytes()).unwrap();
+    }
+    zip.finish().unwrap();
+}
+
+fn write_tar_gz(path: &Path, files: &[(&str, &str)]) {
+    let gz = flate2::write::GzEncoder::new(File::create(path).unwrap(), Default::default());
+    let mut tar = tar::Builder::new(gz);
+    for (name, content) in files {
+        // Raw names so the test can include hostile `..` entries.
+        let mut header = tar::Header::new_gnu();
+        header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        tar.append(&header, content.as_bytes()).unwrap();
+    }
+    tar.into_inner().unwrap().finish().unwrap();
+}
+
+#[test]
+fn archives_can_be_compared_as_input_trees() {
+    let temp = unique_temp_dir("archive-inputs");
+    fs::create_dir_all(&temp).unwrap();
+    let out = temp.join("out");
+    fs::create_dir_all(&out).unwrap();
+
+    write_zip(
+        &temp.join("v1.zip"),
+        &[("app/main.py", "print(1)\n"), ("README", "same\n")],
+    );
+    write_tar_gz(
+        &temp.join("v2.tar.gz"),
+        &[
+            ("app/main.py", "print(2)\n"),
+            ("README", "same\n"),
+            ("../escape.txt", "nope\n"),
+        ],
+    );
+
+    let a = InputTree::open(&temp.join("v1.zip"), &ArchiveLimits::default()).unwrap();
+    let b = InputTree::open(&temp.join("v2.tar.gz"), &ArchiveLimits::default()).unwrap();
+    assert!(a.is_archive() && b.is_archive());
+
+    let counters = run_bigdiff(&a.root, &b.root, &out, &Options::default()).unwrap();
+    assert_eq!(counters.same, 1);
+    assert_eq!(counters.mod_text, 1);
+    assert_eq!(counters.new_files, 0);
+    assert!(out.join("app/main.py.modified").exists());
+    assert!(!temp.join("escape.txt").exists());
+
+    let a_root = a.root.clone();
+    drop(a);
+    assert!(!a_root.exists());
+
+    fs::remove_dir_all(temp).unwrap();
+}
+
+#[test]
+fn recurse_archives_diffs_nested_members() {
+    let temp = unique_temp_dir("nested-archives");
+    let (base, target, out) = (temp.join("base"), temp.join("target"), temp.join("out"));
+    fs::create_dir_all(&out).unwrap();
+
+    write_zip(
+        &base.join("lib/app.jar"),
+        &[("META-INF/MANIFEST.MF", "Version: 1\n"), ("a.class", "x")],
+    );
+    write_zip(
+        &target.join("lib/app.jar"),
+        &[("META-INF/MANIFEST.MF", "Version: 2\n"), ("a.class", "x")],
+    );
+
+    let opts = Options {
+        recurse_archives: true,
+        ..Options::default()
+    };
+    let counters = run_bigdiff(&base, &target, &out, &opts).unwrap();
+    assert_eq!(counters.mod_archive, 1);
+    assert_eq!(counters.mod_text, 1);
+    assert_eq!(counters.same, 1);
+
+    let manifest = out.join("lib/app.jar!/META-INF/MANIFEST.MF.modified");
+    assert_eq!(
+        fs::read_to_string(manifest).unwrap(),
+        "# DELETED: Version: 1\nVersion: 2 # NEW\n"
+    );
+    assert!(!out.join("lib/app.jar.modified").exists());
+
+    fs::remove_dir_all(temp).unwrap();
+}
+
+#[test]
+fn nested_members_are_reported_by_their_archive_path() {
+    let temp = unique_temp_dir("nested-archive-names");
+    let (base, target, out) = (temp.join("base"), temp.join("target"), temp.join("out"));
+    for (root, class) in [(&base, "\0a"), (&target, "\0b")] {
+        write_zip(&root.join("lib/app.jar"), &[("a.class", class)]);
+        fs::write(root.join("lib/broken.zip"), format!("not a zip {class}")).unwrap();
+    }
+
+    let opts = Options {
+        recurse_archives: true,
+        ..Options::default()
+    };
+    let mut warnings = Vec::new();
+    visit_diff(&base, &target, &opts, |event| {
+        if let DiffEvent::Warning { rel, message } = event {
+            warnings.push((rel, message));
+        }
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0].0, Path::new("lib/broken.zip"));
+    assert!(
+        warnings[0]
+            .1
+            .starts_with("Failed to extract archive \"lib/broken.zip\""),
+        "{}",
+        warnings[0].1
+    );
+
+    run_bigdiff(&base, &target, &out, &opts).unwrap();
+    let note = fs::read_to_string(out.join("lib/app.jar!/a.class.modified.NOTE.txt")).unwrap();
+    assert!(note.contains("File: \"lib/app.jar!/a.class\""), "{note}");
+
+    fs::remove_dir_all(temp).unwrap();
+}
+
+fn warnings(base: &Path, target: &Path, opts: &Options) -> Vec<(PathBuf, String)> {
+    let mut warnings = Vec::new();
+    visit_diff(base, target, opts, |event| {
+        if let DiffEvent::Warning { rel, message } = event {
+            warnings.push((rel, message));
+        }
+        Ok(())
+    })
+    .unwrap();
+    warnings
+}
+
+#[test]
+fn archives_over_the_limits_are_compared_as_binaries() {
+    let temp = unique_temp_dir("archive-limits");
+    let (base, target, out) = (temp.join("base"), temp.join("target"), temp.join("out"));
+    let big = "0".repeat(4096);
+    for (root, tail) in [(&base, "a"), (&target, "b")] {
+        write_zip(
+            &root.join("bomb.zip"),
+            &[("zeros", &format!("{big}{tail}"))],
+        );
+        write_zip(
+            &root.join("many.zip"),
+            &[("1", "x"), ("2", "x"), ("3", "x"), ("4", tail)],
+        );
+        // outer.zip!/inner.zip!/a.txt
+        let inner = root.join("inner.zip");
+        write_zip(&inner, &[("a.txt", tail)]);
+        let mut zip = zip::ZipWriter::new(File::create(root.join("outer.zip")).unwrap());
+        zip.start_file("inner.zip", zip::write::SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(&fs::read(&inner).unwrap()).unwrap();
+        zip.finish().unwrap();
+        fs::remove_file(inner).unwrap();
+    }
+
+    let opts = Options {
+        recurse_archives: true,
+        archive_limits: ArchiveLimits {
+            max_bytes: 1024,
+            max_entries: 3,
+            max_depth: 1,
+        },
+        ..Options::default()
+    };
+    let warned = warnings(&base, &target, &opts);
+    let message = |rel: &str| {
+        warned
+            .iter()
+            .find(|(path, _)| path == Path::new(rel))
+            .map(|(_, message)| message.as_str())
+            .unwrap_or_else(|| panic!("no warning for {rel}: {warned:?}"))
+    };
+    assert!(message("bomb.zip").contains("more than 1024 bytes"));
+    assert!(message("many.zip").contains("more than 3 entries"));
+    assert!(message("outer.zip!/inner.zip").contains("limit of 1"));
+    assert_eq!(warned.len(), 3, "{warned:?}");
+
+    let counters = run_bigdiff(&base, &target, &out, &opts).unwrap();
+    assert_eq!(counters.mod_archive, 1);
+    assert_eq!(counters.mod_binary, 3);
+    assert!(out.join("bomb.zip.modified").is_file());
+    assert!(out.join("outer.zip!/inner.zip.modified").is_file());
+
+    // Top-level inputs have no binary fallback, so they fail instead.
+    let err = InputTree::open(&base.join("bomb.zip"), &opts.archive_limits).unwrap_err();
+    assert!(matches!(err, Error::Archive { .. }), "{err}");
+
+    fs::remove_dir_all(temp).unwrap();
+}
+
+#[test]
+fn output_tree_can_be_streamed_into_archives() {
+    let temp = unique_temp_dir("archive-output");
+    let (base, target) = (temp.join("base"), temp.join("target"));
+    fs::create_dir_all(base.join("gone")).unwrap();
+    fs::create_dir_all(&target).unwrap();
+    fs::write(base.join("gone/old.txt"), "bye\n").unwrap();
+    fs::write(base.join("a.txt"), "one\n").unwrap();
+    fs::write(target.join("a.txt"), "two\n").unwrap();
+    fs::write(target.join("b.txt"), "new\n").unwrap();
+
+    let zip_path = temp.join("diff.zip");
+    let opts = Options {
+        output_format: OutputFormat::Zip,
+        ..Options::default()
+    };
+    run_bigdiff(&base, &target, &zip_path, &opts).unwrap();
+    assert!(!temp.join("diff.zip.partial").exists());
+
+    let mut zip = zip::ZipArchive::new(File::open(&zip_path).unwrap()).unwrap();
+    let mut names: Vec<_> = zip.file_names().map(String::from).collect();
+    names.sort();
+    assert_eq!(names.len(), 5);
+    assert_eq!(
+        names[..4],
+        [
+            "a.txt.modified",
+            "b.txt.new",
+            "bigdiff-manifest.json",
+            "gone.deleted/"
+        ]
+    );
+    let mut annotated = String::new();
+    zip.by_name("a.txt.modified")
+        .unwrap()
+        .read_to_string(&mut annotated)
+        .unwrap();
+    assert_eq!(annotated, "# DELETED: one\ntwo # NEW\n");
+
+    let tgz_path = temp.join("diff.tar.gz");
+    let opts = Options {
+        output_format: OutputFormat::TarGz,
+        ..Options::default()
+    };
+    run_bigdiff(&base, &target, &tgz_path, &opts).unwrap();
+
+    let gz = flate2::read::GzDecoder::new(File::open(&tgz_path).unwrap());
+    let mut tar = tar::Archive::new(gz);
+    let mut names: Vec<_> = tar
+        .entries()
+        .unwrap()
+        .map(|e| e.unwrap().path().unwrap().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    assert_eq!(names.len(), 5);
+    assert_eq!(
+        names[..4],
+        [
+            "a.txt.modified",
+            "b.txt.new",
+            "bigdiff-manifest.json",
+            "gone.deleted/"
+        ]
+    );
+
+    fs::remove_dir_all(temp).unwrap();
+}
@@ -0,0 +1,101 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use bigdiff::bindiff::analyze;
+use bigdiff::cli::BinaryReport;
+use bigdiff::utils::sha256_bytes;
+use bigdiff::{run_bigdiff_with_sink, MemorySink, Options};
+
+mod common;
+use common::unique_temp_dir;
+
+/// 100 zero bytes against a copy with bytes 10, 12 and 50 changed and 5 bytes appended.
+fn blobs(temp: &Path) -> (Vec<u8>, Vec<u8>) {
+    let base = vec![0u8; 100];
+    let mut target = base.clone();
+    target[10] = 0xAA;
+    target[12] = 0xBB;
+    target[50] = 0x41;
+    target.extend(b"tail!");
+    for (dir, bytes) in [("base", &base), ("target", &target)] {
+        fs::create_dir_all(temp.join(dir)).unwrap();
+        fs::write(temp.join(dir).join("fw.bin"), bytes).unwrap();
+    }
+    (base, target)
+}
+
+#[test]
+fn ranges_are_coalesced_and_rendered_within_the_budget() {
+    let temp = unique_temp_dir("bindiff-ranges");
+    let (base, target) = blobs(&temp);
+    let (a, b) = (temp.join("base/fw.bin"), temp.join("target/fw.bin"));
+
+    let analysis = analyze(&a, &b).unwrap();
+    assert_eq!(analysis.ranges, [10..13, 50..51, 100..105]);
+    assert_eq!(analysis.differing_bytes, 9);
+    assert_eq!((analysis.base_size, analysis.target_size), (100, 105));
+    assert_eq!(analysis.base_sha256, sha256_bytes(&base));
+    assert_eq!(analysis.target_sha256, sha256_bytes(&target));
+
+    let report = analysis.render(&a, &b, 64 * 1024).unwrap();
+    assert!(
+        report.contains("Differing bytes: 9 in 3 range(s)"),
+        "{report}"
+    );
+    assert!(
+        report.contains("@@ 0x0000000a..0x0000000d (3 bytes) @@"),
+        "{report}"
+    );
+    assert!(
+        report.contains("+ 00000000  00 00 00 00 00 00 00 00 00 00 aa 00 bb 00 00 00"),
+        "{report}"
+    );
+    assert!(
+        report.contains("+ 00000060  00 00 00 00 74 61 69 6c 21"),
+        "{report}"
+    );
+    assert!(report.contains("|....tail!|"), "{report}");
+
+    let short = analysis.render(&a, &b, 400).unwrap();
+    assert!(short.len() <= 400 + 100, "{short}");
+    assert!(
+        short.contains("not shown: report budget of 400 bytes reached"),
+        "{short}"
+    );
+
+    fs::remove_dir_all(temp).unwrap();
+}
+
+#[test]
+fn reports_go_to_the_note_or_a_hexdiff_sidecar() {
+    let temp = unique_temp_dir("bindiff-run");
+    blobs(&temp);
+    let run = |report| {
+        let opts = Options {
+            binary_report: report,
+            ..Options::default()
+        };
+        let mut sink = MemorySink::new();
+        run_bigdiff_with_sink(&temp.join("base"), &temp.join("target"), &mut sink, &opts).unwrap();
+        sink.files()
+            .iter()
+            .map(|(rel, bytes)| (rel.clone(), String::from_utf8_lossy(bytes).into_owned()))
+            .collect::<std::collections::BTreeMap<_, _>>()
+    };
+    let note = |files: &std::collections::BTreeMap<PathBuf, String>| {
+        files[Path::new("fw.bin.modified.NOTE.txt")].clone()
+    };
+
+    let files = run(BinaryReport::Off);
+    assert!(!note(&files).contains("Differing bytes"));
+
+    let files = run(BinaryReport::Note);
+    assert!(note(&files).contains("Differing bytes: 9 in 3 range(s)"));
+    assert!(!files.contains_key(Path::new("fw.bin.modified.hexdiff")));
+
+    let files = run(BinaryReport::Hexdiff);
+    assert!(files[Path::new("fw.bin.modified.hexdiff")].contains("@@ 0x00000032..0x00000033"));
+    assert!(note(&files).contains("Byte-level report: \"fw.bin.modified.hexdiff\""));
+
+    fs::remove_dir_all(temp).unwrap();
+}
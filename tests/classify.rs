@@ -0,0 +1,206 @@
+use std::fs;
+use std::path::Path;
+
+use bigdiff::classify::{Classifier, Reason};
+use bigdiff::{run_bigdiff_with_sink, MemorySink, Options};
+
+mod common;
+use common::unique_temp_dir;
+
+/// Writes `bytes` to `name` in `dir` and classifies it.
+fn classify(classifier: &Classifier, dir: &Path, name: &str, bytes: &[u8]) -> (bool, Reason) {
+    let path = dir.join(name);
+    fs::write(&path, bytes).unwrap();
+    let class = classifier.classify(&path, Path::new(name), None);
+    (class.is_text, class.reason)
+}
+
+#[test]
+fn content_heuristics_look_past_the_first_block() {
+    let temp = unique_temp_dir("classify-content");
+    fs::create_dir_all(&temp).unwrap();
+    let classifier = Classifier::default();
+
+    // Latin-1 is not UTF-8 but is still text.
+    assert_eq!(
+        classify(&classifier, &temp, "latin1", b"caf\xE9 cr\xE8me\n"),
+        (true, Reason::Content)
+    );
+
+    // A text head does not hide a binary tail.
+    let mut tail = "text line\n".repeat(2000).into_bytes();
+    tail.extend([0u8, 1, 2, 3, 0, 0]);
+    assert_eq!(
+        classify(&classifier, &temp, "tail", &tail),
+        (false, Reason::NulBytes)
+    );
+
+    // "ā" is C4 81; both samples cut one of them in half and 0x81 counts as a control byte
+    // unless the UTF-8 check is boundary-safe.
+    let split = format!("x{}", "ā".repeat(5000));
+    assert_eq!(
+        classify(&classifier, &temp, "split", split.as_bytes()),
+        (true, Reason::Content)
+    );
+
+    let noise: Vec<u8> = (0..64u8).map(|i| i % 16 + 1).collect();
+    assert_eq!(
+        classify(&classifier, &temp, "noise", &noise),
+        (false, Reason::ControlCharacters)
+    );
+    assert_eq!(
+        classify(&classifier, &temp, "empty", b""),
+        (true, Reason::Empty)
+    );
+
+    fs::remove_dir_all(temp).unwrap();
+}
+
+#[test]
+fn globs_magic_numbers_and_extensions_take_precedence() {
+    let temp = unique_temp_dir("classify-rules");
+    fs::create_dir_all(&temp).unwrap();
+    let classifier = Classifier::default();
+
+    assert_eq!(
+        classify(&classifier, &temp, "image.txt", b"\x89PNG\r\n\x1a\nplain"),
+        (false, Reason::Magic("PNG"))
+    );
+    assert_eq!(
+        classify(&classifier, &temp, "data.bin", b"plain text\n"),
+        (false, Reason::BinaryExtension)
+    );
+    assert_eq!(
+        classify(&classifier, &temp, "Notes.TXT", b"\x01\x02\x03\x04"),
+        (true, Reason::TextExtension)
+    );
+
+    let forced = Classifier::new(&["*.bin".into()], &["dump*".into()]).unwrap();
+    assert_eq!(
+        classify(&forced, &temp, "data.bin", b"\x89PNG\r\n\x1a\n"),
+        (true, Reason::TextGlob)
+    );
+    assert_eq!(
+        classify(&forced, &temp, "dump.txt", b"plain text\n"),
+        (false, Reason::BinaryGlob)
+    );
+    assert!(Classifier::new(&["[".into()], &[]).is_err());
+
+    fs::remove_dir_all(temp).unwrap();
+}
+
+#[test]
+fn text_starting_with_a_format_name_stays_text() {
+    let temp = unique_temp_dir("classify-ascii-magic");
+    fs::create_dir_all(&temp).unwrap();
+    let classifier = Classifier::default();
+
+    for (name, bytes) in [
+        (
+            "fonts.txt",
+            &b"OTTO is the OpenType tag for CFF fonts\n"[..],
+        ),
+        ("audio.md", b"RIFF chunks start every WAV file\n"),
+        ("tags", b"ID3 tags need rewriting\n"),
+        ("ogg", b"OggS pages\n"),
+        ("flac", b"fLaC streams\n"),
+        ("woff", b"wOFF and wOF2 fonts\n"),
+        ("pdf", b"%PDF-1.7 notes\n"),
+        ("gif", b"GIF89a is the animated variant\n"),
+    ] {
+        let (is_text, reason) = classify(&classifier, &temp, name, bytes);
+        assert!(is_text, "{name}: {reason:?}");
+    }
+
+    // "ustar" at the tar header offset is only a tar when the NUL follows.
+    let mut page = vec![b' '; 257];
+    page.extend(b"ustar is the POSIX archive format\n");
+    assert_eq!(
+        classify(&classifier, &temp, "tar-notes.txt", &page),
+        (true, Reason::TextExtension)
+    );
+    page.splice(262..263, [0u8]);
+    assert_eq!(
+        classify(&classifier, &temp, "real.txt", &page),
+        (false, Reason::Magic("tar"))
+    );
+
+    fs::remove_dir_all(temp).unwrap();
+}
+
+#[test]
+fn latin1_files_without_extension_get_a_text_diff() {
+    let temp = unique_temp_dir("classify-run");
+    for (dir, bytes) in [("base", &b"caf\xE9\n"[..]), ("target", b"caf\xE9 noir\n")] {
+        fs::create_dir_all(temp.join(dir)).unwrap();
+        fs::write(temp.join(dir).join("README"), bytes).unwrap();
+    }
+
+    let mut sink = MemorySink::new();
+    let summary = run_bigdiff_with_sink(
+        &temp.join("base"),
+        &temp.join("target"),
+        &mut sink,
+        &Options::default(),
+    )
+    .unwrap();
+    assert_eq!(summary.mod_text, 1);
+    assert_eq!(summary.mod_binary, 0);
+
+    fs::remove_dir_all(temp).unwrap();
+}
+
+/// Runs an in-memory diff of one file and returns its NOTE.
+fn note_for(tag: &str, base: &[u8], target: &[u8], opts: &Options) -> String {
+    let temp = unique_temp_dir(tag);
+    for (dir, bytes) in [("base", base), ("target", target)] {
+        fs::create_dir_all(temp.join(dir)).unwrap();
+        fs::write(temp.join(dir).join("data"), bytes).unwrap();
+    }
+    let mut sink = MemorySink::new();
+    let summary =
+        run_bigdiff_with_sink(&temp.join("base"), &temp.join("target"), &mut sink, opts).unwrap();
+    assert_eq!(summary.mod_binary, 1);
+    assert_eq!(
+        sink.files()[Path::new("data.modified")],
+        target,
+        "the target is copied"
+    );
+    let note = String::from_utf8(sink.files()[Path::new("data.modified.NOTE.txt")].clone());
+    fs::remove_dir_all(temp).unwrap();
+    note.unwrap()
+}
+
+#[test]
+fn both_sides_are_classified_and_sized() {
+    let opts = Options::default();
+    let note = note_for("classify-to-binary", b"hello\n", b"\x00\x01\x02", &opts);
+    assert!(note.contains("changed from text to binary"), "{note}");
+    assert!(
+        note.contains("Target (B): binary (contains NUL bytes), 3 bytes"),
+        "{note}"
+    );
+
+    let note = note_for("classify-to-text", b"\x00\x01\x02", b"hello\n", &opts);
+    assert!(note.contains("changed from binary to text"), "{note}");
+    assert!(
+        note.contains("Base (A): binary (contains NUL bytes), 3 bytes"),
+        "{note}"
+    );
+
+    // Only the base is over the limit; it still must not be loaded.
+    let opts = Options {
+        max_text_size: 100,
+        ..Options::default()
+    };
+    let big = "line\n".repeat(100);
+    let note = note_for("classify-large", big.as_bytes(), b"line\n", &opts);
+    assert!(
+        note.contains("too large for line diff (limit: 100 bytes)"),
+        "{note}"
+    );
+    assert!(
+        note.contains("Base (A): text (contents look like text), 500 bytes"),
+        "{note}"
+    );
+}
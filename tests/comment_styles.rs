@@ -0,0 +1,161 @@
+use std::path::Path;
+
+use bigdiff::comment::CommentStyle;
+use bigdiff::{Config, Error, StyleRegistry};
+
+mod common;
+use common::unique_temp_dir;
+
+fn prefix_of(style: &CommentStyle) -> &str {
+    match style {
+        CommentStyle::LinePrefix { prefix, .. } => prefix,
+        CommentStyle::Block { open, .. } => open,
+        CommentStyle::Sidecar => "sidecar",
+    }
+}
+
+#[test]
+fn builtin_registry_covers_common_non_hash_languages() {
+    let registry = StyleRegistry::builtin();
+    assert_eq!(
+        prefix_of(registry.style_for(Path::new("ui/App.vue"))),
+        "<!--"
+    );
+    assert_eq!(
+        prefix_of(registry.style_for(Path::new("src/app.erl"))),
+        "% "
+    );
+    assert_eq!(prefix_of(registry.style_for(Path::new("core.clj"))), "; ");
+    assert_eq!(
+        prefix_of(registry.style_for(Path::new("build.bat"))),
+        "REM "
+    );
+    assert_eq!(
+        prefix_of(registry.style_for(Path::new("unknown.xyz"))),
+        "# "
+    );
+}
+
+#[test]
+fn config_mappings_override_builtins_by_precedence() {
+    let config: Config = toml::from_str(
+        r#"
+        default_style = "slash"
+
+        [styles.ocaml]
+        open = "(*"
+        close = "*)"
+
+        [extensions]
+        ".ml" = "ocaml"
+        txt = "semicolon"
+
+        [filenames]
+        "notes.txt" = "dash"
+
+        [globs]
+        "*.vim" = "quote"
+        "#,
+    )
+    .unwrap();
+    let registry = StyleRegistry::from_config(&config).unwrap();
+
+    assert_eq!(prefix_of(registry.style_for(Path::new("lib/a.ml"))), "(*");
+    assert_eq!(prefix_of(registry.style_for(Path::new("a.txt"))), "; ");
+    assert_eq!(
+        prefix_of(registry.style_for(Path::new("docs/notes.txt"))),
+        "-- "
+    );
+    assert_eq!(
+        prefix_of(registry.style_for(Path::new("rc/init.vim"))),
+        "\" "
+    );
+    assert_eq!(
+        prefix_of(registry.style_for(Path::new("unknown.xyz"))),
+        "// "
+    );
+}
+
+#[test]
+fn config_globs_are_tried_in_file_order() {
+    // Alphabetically `*.vim` sorts before `rc/*`; the file order must decide instead.
+    let config: Config = toml::from_str(
+        r#"
+        [globs]
+        "rc/*" = "dash"
+        "*.vim" = "quote"
+        "#,
+    )
+    .unwrap();
+    let registry = StyleRegistry::from_config(&config).unwrap();
+    assert_eq!(
+        prefix_of(registry.style_for(Path::new("rc/init.vim"))),
+        "-- "
+    );
+    assert_eq!(prefix_of(registry.style_for(Path::new("init.vim"))), "\" ");
+}
+
+#[test]
+fn config_rejects_unknown_style_names() {
+    let config: Config = toml::from_str("[extensions]\nvue = \"nope\"\n").unwrap();
+    let err = StyleRegistry::from_config(&config).unwrap_err();
+    assert!(matches!(&err, Error::UnknownStyle { name } if name == "nope"));
+    assert_eq!(err.to_string(), "Unknown comment style in config: nope");
+}
+
+#[test]
+fn detection_uses_filename_shebang_and_modelines() {
+    let dir = unique_temp_dir("style-detect");
+    std::fs::create_dir_all(&dir).unwrap();
+    let registry = StyleRegistry::builtin();
+    let detect = |name: &str, content: &str| {
+        let path = dir.join(name);
+        std::fs::write(&path, content).unwrap();
+        prefix_of(registry.style_for_file(Path::new(name), &path)).to_string()
+    };
+
+    assert_eq!(detect("Jenkinsfile", "pipeline {}\n"), "// ");
+    assert_eq!(detect("CMakeLists.txt", "project(x)\n"), "# ");
+    assert_eq!(detect("serve", "#!/usr/bin/env node\nrun();\n"), "// ");
+    assert_eq!(detect("query", "select 1;\n-- vim: set ft=sql:\n"), "-- ");
+    assert_eq!(
+        detect("notes.txt", "/* -*- mode: c++ -*- */\nint x;\n"),
+        "// "
+    );
+    // A known extension wins over the shebang.
+    assert_eq!(detect("tool.lua", "#!/usr/bin/env node\n"), "-- ");
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn block_deleted_lines_escape_embedded_closers() {
+    let css = bigdiff::comment::comment_style_for(Path::new("a.css"));
+    assert_eq!(
+        css.deleted_line("a { } /* old */\n"),
+        "/* DELETED (escaped): a { } /* old *\\/ */\n"
+    );
+    assert_eq!(css.deleted_line("a { }\n"), "/* DELETED: a { } */\n");
+
+    let html = bigdiff::comment::comment_style_for(Path::new("a.html"));
+    assert_eq!(
+        html.deleted_line("<!-- x --> \\o/"),
+        "<!-- DELETED (escaped): <!-\\- x -\\-> \\\\o/ -->"
+    );
+}
+
+#[test]
+fn validation_flags_annotations_that_change_comment_structure() {
+    use bigdiff::comment::validate_annotation;
+
+    let css = bigdiff::comment::comment_style_for(Path::new("a.css"));
+    let original = "a { color: red; }\n";
+    let deleted = css.deleted_line("b { } /* gone */\n");
+    let annotated = format!("{deleted}{original}");
+    assert!(validate_annotation(&css, original, &annotated));
+
+    // `/* NEW */` appended to a line that opens a comment closes it too early.
+    let original = "/* start\nb { }\n*/\n";
+    let annotated = format!("{}b {{ }}\n*/\n", css.append_new_suffix("/* start\n"));
+    assert!(!validate_annotation(&css, original, &annotated));
+}
@@ -0,0 +1,43 @@
+// Fixtures shared by the integration tests; each test binary uses only some of them.
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bigdiff::{run_bigdiff, Counters, Options};
+
+/// A temp path no other test uses; it is not created.
+pub fn unique_temp_dir(tag: &str) -> PathBuf {
+    let stamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    std::env::temp_dir().join(format!("bigdiff-{tag}-{stamp}"))
+}
+
+/// Creates `base`, `target` and an empty `out` under a fresh temp dir and fills the inputs.
+pub fn setup(tag: &str, base: &[(&str, &str)], target: &[(&str, &str)]) -> PathBuf {
+    let temp = unique_temp_dir(tag);
+    for (dir, files) in [("base", base), ("target", target)] {
+        fs::create_dir_all(temp.join(dir)).unwrap();
+        for (rel, content) in files {
+            let path = temp.join(dir).join(rel);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+    }
+    fs::create_dir_all(temp.join("out")).unwrap();
+    temp
+}
+
+/// Diffs `base` against `target` into `out` under `temp`.
+pub fn run(temp: &Path, opts: &Options) -> Counters {
+    run_bigdiff(
+        &temp.join("base"),
+        &temp.join("target"),
+        &temp.join("out"),
+        opts,
+    )
+    .unwrap()
+}
@@ -0,0 +1,278 @@
+use std::fs;
+
+use bigdiff::delta::{apply, apply_file, apply_tree, encode, read_header, DELTA_MAGIC};
+use bigdiff::utils::sha256_bytes;
+use bigdiff::{run_bigdiff, DeltaError, Error, Options};
+
+mod common;
+use common::unique_temp_dir;
+
+/// Deterministic incompressible bytes.
+fn noise(len: usize, seed: u64) -> Vec<u8> {
+    let mut state = seed;
+    (0..len)
+        .map(|_| {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (state >> 33) as u8
+        })
+        .collect()
+}
+
+/// A "firmware update": a patched word, an inserted block, a removed block and a new tail.
+fn release(base: &[u8]) -> Vec<u8> {
+    let mut target = base[..1000].to_vec();
+    target.extend(b"\xDE\xAD\xBE\xEF");
+    target.extend(&base[1004..20_000]);
+    target.extend(noise(300, 7));
+    target.extend(&base[20_000..40_000]);
+    target.extend(&base[45_000..]);
+    target.extend(b"v2");
+    target
+}
+
+#[test]
+fn deltas_round_trip_and_stay_small() {
+    let base = noise(64 * 1024, 1);
+    let target = release(&base);
+
+    let delta = encode(&base, &target);
+    assert!(delta.len() < 1024, "delta is {} bytes", delta.len());
+    assert_eq!(apply(&base, &delta).unwrap(), target);
+
+    let header = read_header(&delta).unwrap();
+    assert_eq!(header.base_size, base.len() as u64);
+    assert_eq!(hex::encode(header.base_sha256), sha256_bytes(&base));
+    assert_eq!(header.target_size, target.len() as u64);
+
+    // Degenerate inputs still round-trip.
+    for (a, b) in [
+        (&b""[..], &b"abc"[..]),
+        (b"abc", b""),
+        (b"short", b"shorter"),
+    ] {
+        assert_eq!(apply(a, &encode(a, b)).unwrap(), b);
+    }
+}
+
+#[test]
+fn deltas_refuse_the_wrong_base_and_corruption() {
+    let base = noise(4096, 2);
+    let target = release(&noise(64 * 1024, 3));
+    let delta = encode(&base, &target);
+
+    let err = apply(&noise(4096, 4), &delta).unwrap_err();
+    assert!(
+        matches!(err, DeltaError::BaseMismatch { size: 4096, .. }),
+        "{err}"
+    );
+    assert!(matches!(
+        apply(&base, b"not a delta").unwrap_err(),
+        DeltaError::NotADelta
+    ));
+
+    let mut corrupt = delta.clone();
+    let last_literal = corrupt.len() - 2;
+    corrupt[last_literal] ^= 0xFF;
+    assert!(matches!(
+        apply(&base, &corrupt).unwrap_err(),
+        DeltaError::TargetMismatch
+    ));
+}
+
+#[test]
+fn truncated_and_malformed_deltas_are_rejected() {
+    let base = noise(4096, 8);
+    let mut target = base.clone();
+    target.extend(noise(100, 9));
+    let delta = encode(&base, &target);
+    // Magic, two sizes and two hashes.
+    let instructions = DELTA_MAGIC.len() + 80;
+
+    // Every cut, in the header or in the instructions, is detected.
+    for len in 0..delta.len() {
+        let err = apply(&base, &delta[..len]).unwrap_err();
+        match len {
+            len if len < DELTA_MAGIC.len() => assert!(matches!(err, DeltaError::NotADelta)),
+            _ => assert!(matches!(err, DeltaError::Truncated), "{len}: {err}"),
+        }
+    }
+
+    let with = |ops: &[u8]| [&delta[..instructions], ops].concat();
+    assert!(matches!(
+        apply(&base, &with(&[9])).unwrap_err(),
+        DeltaError::UnknownInstruction { tag: 9 }
+    ));
+    // Copy of 16 bytes at offset 4090 of a 4096-byte base.
+    assert!(matches!(
+        apply(&base, &with(&[1, 0xFA, 0x1F, 16, 0])).unwrap_err(),
+        DeltaError::CopyOutsideBase
+    ));
+    assert!(matches!(
+        apply(
+            &base,
+            &with(&[2, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF])
+        )
+        .unwrap_err(),
+        DeltaError::MalformedNumber
+    ));
+    // Ten bytes whose last one carries bits past 63.
+    assert!(matches!(
+        apply(
+            &base,
+            &with(&[2, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x02])
+        )
+        .unwrap_err(),
+        DeltaError::MalformedNumber
+    ));
+    // The header claims 4196 bytes; copying the base twice produces more.
+    assert!(matches!(
+        apply(&base, &with(&[1, 0, 0x80, 0x20, 1, 0, 0x80, 0x20, 0])).unwrap_err(),
+        DeltaError::TargetTooLong { size: 4196 }
+    ));
+    // A huge recorded size allocates nothing up front.
+    let mut huge = delta.clone();
+    let target_size = DELTA_MAGIC.len() + 40; // after the base size and hash
+    huge[target_size..target_size + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+    assert!(matches!(
+        apply(&base, &huge).unwrap_err(),
+        DeltaError::TargetMismatch
+    ));
+
+    let temp = unique_temp_dir("delta-corrupt");
+    fs::create_dir_all(&temp).unwrap();
+    fs::write(temp.join("base.bin"), &base).unwrap();
+    fs::write(temp.join("cut.bdelta"), &delta[..delta.len() - 1]).unwrap();
+    let err = apply_file(
+        &temp.join("base.bin"),
+        &temp.join("cut.bdelta"),
+        &temp.join("out.bin"),
+    )
+    .unwrap_err();
+    assert!(
+        matches!(
+            err,
+            Error::Delta {
+                source: DeltaError::Truncated,
+                ..
+            }
+        ),
+        "{err}"
+    );
+    assert!(!temp.join("out.bin").exists());
+
+    fs::remove_dir_all(temp).unwrap();
+}
+
+#[test]
+fn binary_delta_outputs_rebuild_the_target() {
+    let temp = unique_temp_dir("delta-run");
+    let base = noise(64 * 1024, 5);
+    let target = release(&base);
+    for (dir, files) in [
+        (
+            "base",
+            [(&base, "fw.bin"), (&b"tiny-a".to_vec(), "small.bin")],
+        ),
+        (
+            "target",
+            [(&target, "fw.bin"), (&b"tiny-b".to_vec(), "small.bin")],
+        ),
+    ] {
+        fs::create_dir_all(temp.join(dir)).unwrap();
+        for (bytes, name) in files {
+            fs::write(temp.join(dir).join(name), bytes).unwrap();
+        }
+    }
+
+    let out = temp.join("out");
+    let opts = Options {
+        binary_delta: true,
+        ..Options::default()
+    };
+    let counters = run_bigdiff(&temp.join("base"), &temp.join("target"), &out, &opts).unwrap();
+    assert_eq!(counters.mod_binary, 2);
+
+    assert!(!out.join("fw.bin.modified").exists());
+    let note = fs::read_to_string(out.join("fw.bin.modified.NOTE.txt")).unwrap();
+    assert!(note.contains(&sha256_bytes(&base)), "{note}");
+
+    let rebuilt = temp.join("rebuilt.bin");
+    apply_file(
+        &temp.join("base/fw.bin"),
+        &out.join("fw.bin.modified.bdelta"),
+        &rebuilt,
+    )
+    .unwrap();
+    assert_eq!(fs::read(&rebuilt).unwrap(), target);
+    assert!(apply_file(
+        &temp.join("base/fw.bin"),
+        &out.join("fw.bin.modified.bdelta"),
+        &rebuilt
+    )
+    .is_err());
+
+    // A delta that would not save space is not used.
+    assert_eq!(fs::read(out.join("small.bin.modified")).unwrap(), b"tiny-b");
+    assert!(!out.join("small.bin.modified.bdelta").exists());
+
+    // Files over the size limit are copied without being read into memory for a delta.
+    let out = temp.join("limited");
+    let opts = Options {
+        binary_delta: true,
+        binary_delta_max_size: 1024,
+        ..Options::default()
+    };
+    run_bigdiff(&temp.join("base"), &temp.join("target"), &out, &opts).unwrap();
+    assert_eq!(fs::read(out.join("fw.bin.modified")).unwrap(), target);
+    assert!(!out.join("fw.bin.modified.bdelta").exists());
+    let note = fs::read_to_string(out.join("fw.bin.modified.NOTE.txt")).unwrap();
+    assert!(
+        note.contains("over the binary delta limit of 1024 bytes"),
+        "{note}"
+    );
+
+    fs::remove_dir_all(temp).unwrap();
+}
+
+#[test]
+fn apply_tree_rebuilds_every_delta_of_a_run() {
+    let temp = unique_temp_dir("delta-tree");
+    let (base, target) = (temp.join("base"), temp.join("target"));
+    for (i, name) in ["fw.bin", "sub/boot.bin"].iter().enumerate() {
+        let v1 = noise(64 * 1024, 10 + i as u64);
+        for (root, bytes) in [(&base, v1.clone()), (&target, release(&v1))] {
+            let path = root.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, bytes).unwrap();
+        }
+    }
+
+    let out = temp.join("out");
+    let opts = Options {
+        binary_delta: true,
+        ..Options::default()
+    };
+    run_bigdiff(&base, &target, &out, &opts).unwrap();
+    assert!(out.join("sub/boot.bin.modified.bdelta").is_file());
+
+    let rebuilt = temp.join("rebuilt");
+    let written = apply_tree(&base, &out, &rebuilt).unwrap();
+    assert_eq!(written.len(), 2);
+    for name in ["fw.bin", "sub/boot.bin"] {
+        assert_eq!(
+            fs::read(rebuilt.join(name)).unwrap(),
+            fs::read(target.join(name)).unwrap()
+        );
+    }
+    // Nothing is replaced on a second run.
+    let err = apply_tree(&base, &out, &rebuilt).unwrap_err();
+    assert!(matches!(err, Error::OutputExists { .. }), "{err}");
+
+    // Without a manifest there is nothing to go by.
+    let err = apply_tree(&base, &base, &temp.join("none")).unwrap_err();
+    assert!(matches!(err, Error::Manifest { .. }), "{err}");
+
+    fs::remove_dir_all(temp).unwrap();
+}
@@ -0,0 +1,634 @@
+use std::fs;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use bigdiff::cli::SidecarFormat;
+use bigdiff::{
+    plan_bigdiff, run_bigdiff, run_bigdiff_with_sink, visit_diff, DiffEvent, Error, MemorySink,
+    NullSink, Options, PlannedOutput, Preserve,
+};
+
+mod common;
+use common::{run, setup};
+
+#[test]
+fn comment_less_formats_use_a_sidecar_diff() {
+    let temp = setup(
+        "sidecar",
+        &[("cfg/app.json", "{\n  \"a\": 1\n}\n")],
+        &[("cfg/app.json", "{\n  \"a\": 2\n}\n")],
+    );
+
+    let counters = run(&temp, &Options::default());
+    assert_eq!(counters.mod_text, 1);
+
+    let out = temp.join("out/cfg");
+    assert_eq!(
+        fs::read_to_string(out.join("app.json.modified")).unwrap(),
+        "{\n  \"a\": 2\n}\n"
+    );
+    let diff = fs::read_to_string(out.join("app.json.modified.diff")).unwrap();
+    assert!(diff.contains("--- a/cfg/app.json"));
+    assert!(diff.contains("-  \"a\": 1"));
+    assert!(diff.contains("+  \"a\": 2"));
+
+    fs::remove_dir_all(temp).unwrap();
+}
+
+#[test]
+fn annotations_that_break_block_comments_fall_back_to_a_sidecar() {
+    // The deleted line sits inside an open comment, whose `*/` the marker would close early.
+    let target = "/* start\nb { }\n*/\n";
+    let temp = setup(
+        "validate-annotations",
+        &[("style.css", "/* start\na { }\nb { }\n*/\n")],
+        &[("style.css", target)],
+    );
+
+    run(&temp, &Options::default());
+    let out = temp.join("out");
+    assert_eq!(
+        fs::read_to_string(out.join("style.css.modified")).unwrap(),
+        target
+    );
+    assert!(out.join("style.css.modified.diff").is_file());
+    let note = fs::read_to_string(out.join("style.css.modified.NOTE.txt")).unwrap();
+    assert!(note.contains("failed comment validation"), "{note}");
+
+    // Opting out keeps the inline markers.
+    let opts = Options {
+        validate_annotations: false,
+        ..Options::default()
+    };
+    fs::remove_dir_all(&out).unwrap();
+    run(&temp, &opts);
+    assert!(fs::read_to_string(out.join("style.css.modified"))
+        .unwrap()
+        .contains("DELETED"));
+
+    fs::remove_dir_all(temp).unwrap();
+}
+
+#[test]
+fn sidecar_can_be_written_as_json_hunks() {
+    let temp = setup(
+        "sidecar-json",
+        &[("a.csv", "id\n1\n")],
+        &[("a.csv", "id\n2\n")],
+    );
+
+    let opts = Options {
+        sidecar_format: SidecarFormat::Json,
+        ..Options::default()
+    };
+    run(&temp, &opts);
+
+    let hunks = fs::read_to_string(temp.join("out/a.csv.modified.hunks.json")).unwrap();
+    let hunks: serde_json::Value = serde_json::from_str(&hunks).unwrap();
+    assert_eq!(hunks[0]["old_start"], 1);
+    assert_eq!(hunks[0]["lines"][1]["tag"], "-");
+    assert_eq!(hunks[0]["lines"][1]["text"], "1");
+
+    fs::remove_dir_all(temp).unwrap();
+}
+
+#[test]
+fn semantic_mode_ignores_reordering_and_reports_key_paths() {
+    let temp = setup(
+        "semantic",
+        &[
+            ("a.yaml", "b: 1\na: [x, y]\n"),
+            ("svc.json", "{\"services\": {\"api\": {\"image\": \"v1\"}}}"),
+            ("c.toml", "[x]\nk = 1\n"),
+        ],
+        &[
+            ("a.yaml", "a:\n  - x\n  - y\nb: 1\n"),
+            (
+                "svc.json",
+                "{\n  \"services\": {\n    \"api\": {\"image\": \"v2\", \"port\": 80}\n  }\n}\n",
+            ),
+            ("c.toml", "[x]\nk = 2\n"),
+        ],
+    );
+
+    let opts = Options {
+        semantic: true,
+        ..Options::default()
+    };
+    let counters = run(&temp, &opts);
+    assert_eq!(counters.same, 1);
+    assert_eq!(counters.mod_text, 2);
+    assert!(!temp.join("out/a.yaml.modified").exists());
+
+    let report = fs::read_to_string(temp.join("out/svc.json.modified.SEMANTIC.txt")).unwrap();
+    assert_eq!(
+        report,
+        "$.services.api.image: \"v1\" -> \"v2\"\n$.services.api.port: added 80\n"
+    );
+    let diff = fs::read_to_string(temp.join("out/svc.json.modified.diff")).unwrap();
+    assert!(diff.starts_with("SEMANTIC: $.services.api.image: \"v1\" -> \"v2\"\n"));
+
+    let toml = fs::read_to_string(temp.join("out/c.toml.modified")).unwrap();
+    assert!(toml.starts_with("# SEMANTIC: $.x.k: 1 -> 2\n"));
+
+    fs::remove_dir_all(temp).unwrap();
+}
+
+#[test]
+fn semantic_mode_warns_when_it_falls_back_to_lines() {
+    let temp = setup(
+        "semantic-fallback",
+        &[
+            ("multi.yaml", "a: 1\n---\nb: 2\n"),
+            ("when.toml", "at = 1979-05-27T07:32:00Z\n"),
+        ],
+        &[
+            ("multi.yaml", "a: 1\n---\nb: 3\n"),
+            ("when.toml", "at = 1979-05-27T08:00:00Z\n"),
+        ],
+    );
+
+    let opts = Options {
+        semantic: true,
+        ..Options::default()
+    };
+    let mut warnings = Vec::new();
+    visit_diff(&temp.join("base"), &temp.join("target"), &opts, |event| {
+        if let DiffEvent::Warning { rel, message } = event {
+            warnings.push((rel, message));
+        }
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(warnings.len(), 1, "{warnings:?}");
+    assert_eq!(warnings[0].0, Path::new("multi.yaml"));
+    assert!(warnings[0].1.starts_with("Invalid YAML document: "));
+    assert!(warnings[0].1.ends_with("; compared line by line"));
+
+    run(&temp, &opts);
+    let toml = fs::read_to_string(temp.join("out/when.toml.modified")).unwrap();
+    assert!(
+        toml.starts_with(
+            "# SEMANTIC: $.at: \"1979-05-27T07:32:00Z\" -> \"1979-05-27T08:00:00Z\"\n"
+        ),
+        "{toml}"
+    );
+
+    fs::remove_dir_all(temp).unwrap();
+}
+
+#[test]
+fn csv_key_compares_rows_regardless_of_order() {
+    let temp = setup(
+        "csv-key",
+        &[
+            ("sorted.csv", "id,name,qty\n1,a,5\n2,b,6\n"),
+            ("data.tsv", "id\tname\tqty\n1\ta\t5\n2\tb\t6\n3\tc\t7\n"),
+        ],
+        &[
+            ("sorted.csv", "qty,id,name\n6,2,b\n5,1,a\n"),
+            ("data.tsv", "name\tid\tqty\n\"b\"\t2\t9\nd\t4\t1\na\t1\t5\n"),
+        ],
+    );
+
+    let opts = Options {
+        csv_keys: vec!["id".into()],
+        ..Options::default()
+    };
+    let counters = run(&temp, &opts);
+    assert_eq!(counters.same, 1);
+    assert_eq!(counters.mod_text, 1);
+
+    let report = fs::read_to_string(temp.join("out/data.tsv.modified.rows.csv")).unwrap();
+    assert_eq!(
+        report,
+        "change,id,column,old,new\n\
+cell_changed,2,qty,6,9\n\
+row_removed,3,name,c,\n\
+row_removed,3,qty,7,\n\
+row_added,4,name,,d\n\
+row_added,4,qty,,1\n"
+    );
+    assert!(!temp.join("out/data.tsv.modified.diff").exists());
+
+    fs::remove_dir_all(temp).unwrap();
+}
+
+#[test]
+fn canonical_xml_ignores_attribute_order_and_prefixes() {
+    let temp = setup(
+        "canonical-xml",
+        &[
+            (
+                "icon.svg",
+                "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"1\" height=\"2\">\n  <g><path d=\"M0\"/></g>\n</svg>\n",
+            ),
+            (
+                "pom.xml",
+                "<a:project xmlns:a=\"urn:p\"><a:dep id=\"x\">1.0</a:dep><a:dep id=\"y\"/></a:project>",
+            ),
+        ],
+        &[
+            (
+                "icon.svg",
+                "<s:svg height=\"2\"   width=\"1\" xmlns:s=\"http://www.w3.org/2000/svg\"><s:g>\n<s:path d=\"M0\"/></s:g></s:svg>",
+            ),
+            (
+                "pom.xml",
+                "<b:project xmlns:b=\"urn:p\">\n  <b:dep id=\"x\"> 2.0 </b:dep>\n  <b:dep id=\"z\"/>\n</b:project>\n",
+            ),
+        ],
+    );
+
+    let opts = Options {
+        canonical_xml: true,
+        ..Options::default()
+    };
+    let counters = run(&temp, &opts);
+    assert_eq!(counters.same, 1);
+    assert_eq!(counters.mod_text, 1);
+
+    let report = fs::read_to_string(temp.join("out/pom.xml.modified.SEMANTIC.txt")).unwrap();
+    assert_eq!(
+        report,
+        "/project/dep[1]/text(): \"1.0\" -> \"2.0\"\n/project/dep[2]/@id: \"y\" -> \"z\"\n"
+    );
+    let annotated = fs::read_to_string(temp.join("out/pom.xml.modified")).unwrap();
+    assert!(annotated.starts_with("<!-- SEMANTIC: /project/dep[1]/text()"));
+
+    fs::remove_dir_all(temp).unwrap();
+}
+
+#[test]
+fn canonical_xml_keeps_preserved_and_attribute_whitespace() {
+    let temp = setup(
+        "canonical-xml-space",
+        &[(
+            "doc.xml",
+            "<doc><code xml:space=\"preserve\">a  b</code><p>x  y</p><e title=\"a  b\"/></doc>",
+        )],
+        &[(
+            "doc.xml",
+            "<doc><code xml:space=\"preserve\">a b</code><p>x y</p><e title=\"a b\"/></doc>",
+        )],
+    );
+
+    let opts = Options {
+        canonical_xml: true,
+        ..Options::default()
+    };
+    run(&temp, &opts);
+    let report = fs::read_to_string(temp.join("out/doc.xml.modified.SEMANTIC.txt")).unwrap();
+    assert_eq!(
+        report,
+        "/doc/code/text(): \"a  b\" -> \"a b\"\n/doc/e/@title: \"a  b\" -> \"a b\"\n"
+    );
+
+    fs::remove_dir_all(temp).unwrap();
+}
+
+#[test]
+fn memory_and_null_sinks_mirror_the_filesystem_run() {
+    let temp = setup(
+        "sinks",
+        &[
+            ("keep.txt", "same\n"),
+            ("gone.txt", "old\n"),
+            ("src/a.py", "x = 1\n"),
+        ],
+        &[
+            ("keep.txt", "same\n"),
+            ("fresh.txt", "new\n"),
+            ("src/a.py", "x = 2\n"),
+        ],
+    );
+    let opts = Options::default();
+    let (base, target) = (temp.join("base"), temp.join("target"));
+
+    let mut memory = MemorySink::new();
+    let in_memory = run_bigdiff_with_sink(&base, &target, &mut memory, &opts).unwrap();
+    let mut null = NullSink::new();
+    let dry = run_bigdiff_with_sink(&base, &target, &mut null, &opts).unwrap();
+    assert_eq!(fs::read_dir(temp.join("out")).unwrap().count(), 0);
+
+    let on_disk = run(&temp, &opts);
+    for counters in [&in_memory, &dry] {
+        assert_eq!(counters.new_files, on_disk.new_files);
+        assert_eq!(counters.del_files, on_disk.del_files);
+        assert_eq!(counters.mod_text, on_disk.mod_text);
+        assert_eq!(counters.same, on_disk.same);
+    }
+
+    assert_eq!(memory.files().len(), 3);
+    for (rel, contents) in memory.files() {
+        assert_eq!(&fs::read(temp.join("out").join(rel)).unwrap(), contents);
+    }
+    assert_eq!(memory.get("fresh.txt.new"), Some(&b"new\n"[..]));
+}
+
+#[test]
+fn visitor_receives_typed_events_without_writing() {
+    let temp = setup(
+        "events",
+        &[
+            ("keep.txt", "same\n"),
+            ("old/gone.txt", "bye\n"),
+            ("a.txt", "one\ntwo\n"),
+            ("blob.bin", "\0\x01"),
+        ],
+        &[
+            ("keep.txt", "same\n"),
+            ("fresh.txt", "hi\n"),
+            ("a.txt", "one\nTWO\n"),
+            ("blob.bin", "\0\x02"),
+        ],
+    );
+
+    let mut events = Vec::new();
+    visit_diff(
+        &temp.join("base"),
+        &temp.join("target"),
+        &Options::default(),
+        |event| {
+            events.push(event);
+            Ok(())
+        },
+    )
+    .unwrap();
+    assert_eq!(fs::read_dir(temp.join("out")).unwrap().count(), 0);
+
+    let kinds: Vec<(&str, String)> = events
+        .iter()
+        .map(|event| {
+            let kind = match event {
+                DiffEvent::Equal { .. } => "equal",
+                DiffEvent::Added { .. } => "added",
+                DiffEvent::Deleted { .. } => "deleted",
+                DiffEvent::DirDeleted { .. } => "dir-deleted",
+                DiffEvent::Modified { .. } => "modified",
+                DiffEvent::BinaryModified { .. } => "binary",
+                DiffEvent::ArchiveModified { .. } => "archive",
+                DiffEvent::Warning { .. } => "warning",
+                DiffEvent::Error { .. } => "error",
+            };
+            (kind, event.rel().to_string_lossy().replace('\\', "/"))
+        })
+        .collect();
+    assert_eq!(kinds[0], ("dir-deleted", "old".to_string()));
+    assert_eq!(kinds[1], ("deleted", "old/gone.txt".to_string()));
+    assert!(kinds.contains(&("added", "fresh.txt".to_string())));
+    assert!(kinds.contains(&("equal", "keep.txt".to_string())));
+    assert!(kinds.contains(&("binary", "blob.bin".to_string())));
+
+    let hunks = events.iter().find_map(DiffEvent::hunks).unwrap();
+    assert_eq!(hunks.len(), 1);
+    assert_eq!(hunks[0].old_start, 1);
+    let changed: Vec<_> = hunks[0]
+        .lines
+        .iter()
+        .filter(|line| line.tag != " ")
+        .map(|line| format!("{}{}", line.tag, line.text))
+        .collect();
+    assert_eq!(changed, ["-two", "+TWO"]);
+}
+
+// `/proc/self/mem` is a regular file whose first page is unmapped, so reading it always fails,
+// even as root.
+#[cfg(target_os = "linux")]
+#[test]
+fn keep_going_records_unreadable_files_and_continues() {
+    let temp = setup(
+        "keep-going",
+        &[("broken.txt", "old\n"), ("a.txt", "one\n")],
+        &[("a.txt", "two\n")],
+    );
+    let unreadable = Path::new("/proc/self/mem");
+    std::os::unix::fs::symlink(unreadable, temp.join("target/broken.txt")).unwrap();
+    std::os::unix::fs::symlink(unreadable, temp.join("target/fresh.txt")).unwrap();
+
+    let err = run_bigdiff(
+        &temp.join("base"),
+        &temp.join("target"),
+        &temp.join("out"),
+        &Options::default(),
+    )
+    .unwrap_err();
+    assert!(matches!(err, bigdiff::Error::Io { .. }), "{err}");
+
+    fs::remove_dir_all(temp.join("out")).unwrap();
+    fs::create_dir_all(temp.join("out")).unwrap();
+    let opts = Options {
+        keep_going: true,
+        ..Options::default()
+    };
+    let counters = run(&temp, &opts);
+    assert_eq!(counters.errors, 2);
+    assert_eq!(counters.mod_text, 1);
+    assert!(temp.join("out/a.txt.modified").exists());
+
+    let text = fs::read_to_string(temp.join("out/ERRORS.txt")).unwrap();
+    assert!(text.contains("broken.txt: I/O error"));
+    assert!(text.contains("fresh.txt: I/O error"));
+    let json: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(temp.join("out/ERRORS.json")).unwrap()).unwrap();
+    assert_eq!(json.as_array().unwrap().len(), 2);
+    assert!(!temp.join("out/broken.txt.modified").exists());
+}
+
+// Root reads folders regardless of their mode, so this only checks something for other users.
+#[cfg(unix)]
+#[test]
+fn keep_going_records_unreadable_folders() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp = setup(
+        "unreadable-dir",
+        &[("a.txt", "one\n"), ("gone/old.txt", "x\n")],
+        &[("a.txt", "two\n"), ("locked/x.txt", "x\n")],
+    );
+    let locked = temp.join("target/locked");
+    fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+    if fs::read_dir(&locked).is_ok() {
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+        fs::remove_dir_all(temp).unwrap();
+        return;
+    }
+
+    let err = run_bigdiff(
+        &temp.join("base"),
+        &temp.join("target"),
+        &temp.join("out"),
+        &Options::default(),
+    )
+    .unwrap_err();
+    assert!(
+        matches!(err, bigdiff::Error::Io { ref path, .. } if path == &locked),
+        "{err}"
+    );
+
+    fs::remove_dir_all(temp.join("out")).unwrap();
+    fs::create_dir_all(temp.join("out")).unwrap();
+    let opts = Options {
+        keep_going: true,
+        ..Options::default()
+    };
+    let counters = run(&temp, &opts);
+    assert_eq!(counters.errors, 1);
+    assert_eq!(counters.mod_text, 1);
+    assert_eq!(counters.del_files, 1);
+    assert!(temp.join("out/a.txt.modified").exists());
+    assert!(temp.join("out/gone.deleted/old.txt.deleted").exists());
+
+    let text = fs::read_to_string(temp.join("out/ERRORS.txt")).unwrap();
+    assert!(text.contains("locked: I/O error"), "{text}");
+    let json: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(temp.join("out/ERRORS.json")).unwrap()).unwrap();
+    assert_eq!(json.as_array().unwrap().len(), 1);
+
+    fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+    fs::remove_dir_all(temp).unwrap();
+}
+
+#[test]
+fn dry_run_plan_matches_the_real_run_including_escapes() {
+    let temp = setup(
+        "plan",
+        &[
+            ("d/x", "gone\n"),
+            ("d.deleted/x.deleted", "also gone\n"),
+            ("a.txt", "one\n"),
+        ],
+        &[("d.deleted/keep", "k\n"), ("a.txt", "two\n")],
+    );
+    let opts = Options::default();
+
+    let plan = plan_bigdiff(&temp.join("base"), &temp.join("target"), &opts).unwrap();
+    assert_eq!(fs::read_dir(temp.join("out")).unwrap().count(), 0);
+
+    let outputs_of = |rel: &str| -> Vec<String> {
+        plan.actions
+            .iter()
+            .find(|action| action.rel == Path::new(rel))
+            .unwrap()
+            .outputs
+            .iter()
+            .map(|output| output.path().to_string_lossy().replace('\\', "/"))
+            .collect()
+    };
+    assert_eq!(outputs_of("d/x"), ["d.deleted/x.deleted"]);
+    assert_eq!(
+        outputs_of("d.deleted/x.deleted"),
+        ["d.deleted~/x.deleted~.deleted"]
+    );
+    assert_eq!(outputs_of("a.txt"), ["a.txt.modified"]);
+    assert!(plan.actions.iter().all(|action| action.action != "equal"));
+
+    let counters = run(&temp, &opts);
+    assert_eq!(counters.del_files, plan.counters.del_files);
+    assert_eq!(counters.mod_text, plan.counters.mod_text);
+    for action in &plan.actions {
+        for output in &action.outputs {
+            let path = temp.join("out").join(output.path());
+            match output {
+                PlannedOutput::CreateDir { .. } => assert!(path.is_dir()),
+                _ => assert!(path.is_file(), "{path:?}"),
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn preserve_copies_mode_and_mtime_from_the_source() {
+    use std::os::unix::fs::PermissionsExt;
+    use std::time::Duration;
+
+    let temp = setup(
+        "preserve",
+        &[("run.sh", "echo one\n"), ("old.sh", "echo old\n")],
+        &[("run.sh", "echo two\n"), ("new.sh", "echo new\n")],
+    );
+    let stamp = UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+    for rel in ["base/old.sh", "target/run.sh", "target/new.sh"] {
+        let path = temp.join(rel);
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o750)).unwrap();
+        let file = fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(stamp).unwrap();
+    }
+
+    let opts = Options {
+        preserve: Preserve {
+            mode: true,
+            mtime: true,
+        },
+        ..Options::default()
+    };
+    run(&temp, &opts);
+
+    // The annotated text output is written fresh, yet still carries the target's metadata.
+    for rel in ["run.sh.modified", "new.sh.new", "old.sh.deleted"] {
+        let meta = fs::metadata(temp.join("out").join(rel)).unwrap();
+        assert_eq!(meta.permissions().mode() & 0o777, 0o750, "{rel}");
+        assert_eq!(meta.modified().unwrap(), stamp, "{rel}");
+    }
+    assert!(fs::read_to_string(temp.join("out/run.sh.modified"))
+        .unwrap()
+        .contains("# NEW"));
+
+    fs::remove_dir_all(temp).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn preserve_mtime_handles_read_only_sources() {
+    use std::os::unix::fs::PermissionsExt;
+    use std::time::Duration;
+
+    let temp = setup("preserve-read-only", &[], &[("locked.txt", "frozen\n")]);
+    let path = temp.join("target/locked.txt");
+    let stamp = UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+    fs::File::options()
+        .write(true)
+        .open(&path)
+        .unwrap()
+        .set_modified(stamp)
+        .unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o444)).unwrap();
+
+    for mode in [false, true] {
+        let out = temp.join(format!("out-{mode}"));
+        let opts = Options {
+            preserve: Preserve { mode, mtime: true },
+            ..Options::default()
+        };
+        run_bigdiff(&temp.join("base"), &temp.join("target"), &out, &opts).unwrap();
+        let meta = fs::metadata(out.join("locked.txt.new")).unwrap();
+        assert_eq!(meta.modified().unwrap(), stamp, "mode: {mode}");
+        assert_eq!(meta.permissions().mode() & 0o777, 0o444, "mode: {mode}");
+    }
+
+    fs::remove_dir_all(temp).unwrap();
+}
+
+#[test]
+fn sinks_without_metadata_refuse_preserve() {
+    let temp = setup("preserve-memory", &[], &[("new.txt", "fresh\n")]);
+    let opts = Options {
+        preserve: Preserve {
+            mode: false,
+            mtime: true,
+        },
+        ..Options::default()
+    };
+
+    let mut sink = MemorySink::new();
+    let err = run_bigdiff_with_sink(&temp.join("base"), &temp.join("target"), &mut sink, &opts)
+        .unwrap_err();
+    assert!(matches!(&err, Error::MetadataUnsupported { .. }), "{err}");
+
+    // A dry run only plans the copies.
+    let mut sink = NullSink::new();
+    run_bigdiff_with_sink(&temp.join("base"), &temp.join("target"), &mut sink, &opts).unwrap();
+
+    fs::remove_dir_all(temp).unwrap();
+}
@@ -0,0 +1,78 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use bigdiff::encoding::{EncodingRules, TextEncoding};
+use bigdiff::{run_bigdiff, Options};
+
+mod common;
+use common::unique_temp_dir;
+
+/// Writes one file on each side and runs a diff into `out`.
+fn run(tag: &str, base: &[u8], target: &[u8], opts: &Options) -> (PathBuf, PathBuf) {
+    let temp = unique_temp_dir(tag);
+    for (dir, bytes) in [("base", base), ("target", target)] {
+        fs::create_dir_all(temp.join(dir)).unwrap();
+        fs::write(temp.join(dir).join("notes.txt"), bytes).unwrap();
+    }
+    let out = temp.join("out");
+    run_bigdiff(&temp.join("base"), &temp.join("target"), &out, opts).unwrap();
+    (temp, out)
+}
+
+fn utf16le_with_bom(text: &str) -> Vec<u8> {
+    let mut bytes = vec![0xFF, 0xFE];
+    bytes.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+    bytes
+}
+
+fn read_note(out: &Path) -> String {
+    fs::read_to_string(out.join("notes.txt.modified.NOTE.txt")).unwrap()
+}
+
+#[test]
+fn utf16_with_bom_is_diffed_as_text_and_written_back_as_utf16() {
+    let (temp, out) = run(
+        "encoding-utf16",
+        &utf16le_with_bom("keep\nold\n"),
+        &utf16le_with_bom("keep\nnew\n"),
+        &Options::default(),
+    );
+
+    let bytes = fs::read(out.join("notes.txt.modified")).unwrap();
+    assert!(bytes.starts_with(&[0xFF, 0xFE]));
+    let text = TextEncoding::detect(&bytes, None).decode(&bytes);
+    assert!(text.contains("# DELETED: old"), "{text}");
+    assert!(text.contains("new # NEW"), "{text}");
+    assert!(read_note(&out).contains("Output encoding: UTF-16LE with BOM"));
+
+    fs::remove_dir_all(temp).unwrap();
+}
+
+#[test]
+fn encoding_override_round_trips_single_byte_text() {
+    let opts = Options {
+        encodings: EncodingRules::parse(&["*.txt=latin1".into()]).unwrap(),
+        ..Options::default()
+    };
+    let (temp, out) = run("encoding-latin1", b"caf\xE9\n", b"caf\xE9 noir\n", &opts);
+
+    let bytes = fs::read(out.join("notes.txt.modified")).unwrap();
+    assert!(bytes.windows(9).any(|w| w == b"caf\xE9 noir"), "{bytes:?}");
+    assert!(read_note(&out).contains("windows-1252"));
+
+    fs::remove_dir_all(temp).unwrap();
+}
+
+#[test]
+fn utf16_without_bom_is_sniffed_and_unknown_labels_are_rejected() {
+    let bytes: Vec<u8> = "hello\nworld\n"
+        .encode_utf16()
+        .flat_map(u16::to_be_bytes)
+        .collect();
+    let detected = TextEncoding::detect(&bytes, None);
+    assert_eq!(detected.encoding, encoding_rs::UTF_16BE);
+    assert!(!detected.bom);
+    assert_eq!(detected.decode(&bytes), "hello\nworld\n");
+
+    assert!(EncodingRules::parse(&["no-such-encoding".into()]).is_err());
+}
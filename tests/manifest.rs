@@ -0,0 +1,90 @@
+use std::fs;
+use std::path::Path;
+
+use bigdiff::cli::OnExisting;
+use bigdiff::manifest::{EntryKind, Manifest, ManifestEntry};
+use bigdiff::utils::sha256_file;
+use bigdiff::{run_bigdiff, Options};
+
+mod common;
+use common::unique_temp_dir;
+
+fn entry<'a>(manifest: &'a Manifest, path: &str) -> &'a ManifestEntry {
+    manifest
+        .outputs
+        .iter()
+        .find(|e| e.path == Path::new(path))
+        .unwrap_or_else(|| panic!("{path} missing from {:#?}", manifest.outputs))
+}
+
+#[test]
+fn manifest_maps_outputs_to_sources() {
+    let temp = unique_temp_dir("manifest");
+    let (base, target, out) = (temp.join("base"), temp.join("target"), temp.join("out"));
+    fs::create_dir_all(base.join("sub")).unwrap();
+    fs::create_dir_all(target.join("sub")).unwrap();
+    fs::write(base.join("sub/a.txt"), "one\n").unwrap();
+    fs::write(target.join("sub/a.txt"), "two\n").unwrap();
+    fs::write(base.join("gone.txt"), "bye\n").unwrap();
+    fs::write(target.join("new.txt"), "fresh\n").unwrap();
+
+    run_bigdiff(&base, &target, &out, &Options::default()).unwrap();
+    let manifest = Manifest::load(&out).unwrap().unwrap();
+    assert_eq!(manifest.version, env!("CARGO_PKG_VERSION"));
+    assert_eq!(
+        manifest.options.as_ref().unwrap().on_existing,
+        OnExisting::Error
+    );
+
+    let modified = entry(&manifest, "sub/a.txt.modified");
+    assert_eq!(modified.kind, EntryKind::File);
+    assert_eq!(modified.category.as_deref(), Some("modified"));
+    let (from, to) = (
+        modified.base.as_ref().unwrap(),
+        modified.target.as_ref().unwrap(),
+    );
+    assert_eq!(from.path, Path::new("sub/a.txt"));
+    assert_eq!(from.sha256, sha256_file(&base.join("sub/a.txt")).unwrap());
+    assert_eq!(to.size, 4);
+    let written = out.join("sub/a.txt.modified");
+    assert_eq!(
+        modified.sha256.as_deref(),
+        Some(sha256_file(&written).unwrap().as_str())
+    );
+    assert_eq!(modified.size, Some(fs::metadata(&written).unwrap().len()));
+
+    let deleted = entry(&manifest, "gone.txt.deleted");
+    assert_eq!(deleted.category.as_deref(), Some("deleted"));
+    assert!(deleted.target.is_none());
+    assert_eq!(
+        deleted.base.as_ref().unwrap().sha256,
+        deleted.sha256.clone().unwrap()
+    );
+
+    assert_eq!(entry(&manifest, "sub").kind, EntryKind::Dir);
+
+    // A merged rerun into a folder holding other files lists them as they were.
+    let merged_out = temp.join("merged");
+    fs::create_dir_all(&merged_out).unwrap();
+    fs::write(merged_out.join("mine.txt"), "mine\n").unwrap();
+    let merged = Options {
+        on_existing: OnExisting::Merge,
+        ..Options::default()
+    };
+    run_bigdiff(&base, &target, &merged_out, &merged).unwrap();
+    let manifest = Manifest::load(&merged_out).unwrap().unwrap();
+    assert_eq!(
+        entry(&manifest, "new.txt.new")
+            .target
+            .as_ref()
+            .unwrap()
+            .path,
+        Path::new("new.txt")
+    );
+    assert!(manifest
+        .outputs
+        .iter()
+        .all(|e| e.path != Path::new("mine.txt")));
+
+    fs::remove_dir_all(temp).unwrap();
+}
@@ -0,0 +1,174 @@
+use std::ffi::OsStr;
+use std::fs;
+use std::path::Path;
+
+use bigdiff::cli::OnExisting;
+use bigdiff::naming::escape_name;
+use bigdiff::{run_bigdiff, run_bigdiff_with_sink, visit_diff, MemorySink, Options};
+
+mod common;
+use common::setup;
+
+/// Output files of an in-memory run, as `/`-separated strings with their contents.
+fn outputs(temp: &Path) -> Vec<(String, String)> {
+    let mut sink = MemorySink::new();
+    run_bigdiff_with_sink(
+        &temp.join("base"),
+        &temp.join("target"),
+        &mut sink,
+        &Options::default(),
+    )
+    .unwrap();
+    sink.files()
+        .iter()
+        .map(|(rel, bytes)| {
+            (
+                rel.to_string_lossy().replace('\\', "/"),
+                String::from_utf8_lossy(bytes).into_owned(),
+            )
+        })
+        .collect()
+}
+
+fn escaped(name: &str) -> String {
+    escape_name(OsStr::new(name)).to_string_lossy().into_owned()
+}
+
+#[test]
+fn escaping_only_touches_names_that_look_like_outputs() {
+    assert_eq!(escaped("a.txt"), "a.txt");
+    assert_eq!(escaped("a.txt.new"), "a.txt.new~");
+    assert_eq!(escaped("a.deleted"), "a.deleted~");
+    assert_eq!(escaped("a.modified"), "a.modified~");
+    assert_eq!(escaped("a.modified.NOTE.txt"), "a.modified.NOTE.txt~");
+    assert_eq!(escaped("app.jar!"), "app.jar!~");
+    assert_eq!(escaped("notes~"), "notes~~");
+    assert_eq!(escaped("ERRORS.txt"), "ERRORS.txt~");
+    assert_eq!(escaped("bigdiff-manifest.json"), "bigdiff-manifest.json~");
+    // Only the full companion suffix is reserved.
+    assert_eq!(escaped("patch.diff"), "patch.diff");
+    assert_eq!(escaped("NOTE.txt"), "NOTE.txt");
+}
+
+#[test]
+fn marker_lookalikes_in_the_sources_get_their_own_outputs() {
+    let temp = setup(
+        "naming-lookalikes",
+        &[("b.txt", "one\n"), ("c.txt", "old\n")],
+        &[
+            ("a.txt", "plain\n"),
+            ("a.txt.new", "lookalike\n"),
+            ("b.txt", "two\n"),
+            ("b.txt.modified/inner.txt", "dir\n"),
+            ("notes", "n\n"),
+            ("notes~", "backup\n"),
+        ],
+    );
+    let files = outputs(&temp);
+    let get = |name: &str| {
+        files
+            .iter()
+            .find(|(rel, _)| rel == name)
+            .unwrap_or_else(|| panic!("{name} missing from {files:?}"))
+            .1
+            .as_str()
+    };
+
+    assert_eq!(get("a.txt.new"), "plain\n");
+    assert_eq!(get("a.txt.new~.new"), "lookalike\n");
+    assert!(get("b.txt.modified").contains("two"));
+    assert_eq!(get("b.txt.modified~/inner.txt.new"), "dir\n");
+    assert_eq!(get("c.txt.deleted"), "old\n");
+    assert_eq!(get("notes.new"), "n\n");
+    assert_eq!(get("notes~~.new"), "backup\n");
+    assert_eq!(files.len(), 7);
+
+    fs::remove_dir_all(temp).unwrap();
+}
+
+#[test]
+fn deleted_folders_and_reserved_names_do_not_collide() {
+    let temp = setup(
+        "naming-deleted",
+        &[
+            ("d/x", "gone\n"),
+            ("d/sub/y", "deep\n"),
+            ("d.deleted/x.deleted", "also gone\n"),
+            ("ERRORS.txt/log", "old log\n"),
+        ],
+        &[("d.deleted/keep", "k\n"), ("ERRORS.txt/log", "new log\n")],
+    );
+    let names: Vec<_> = outputs(&temp).into_iter().map(|(rel, _)| rel).collect();
+    assert_eq!(
+        names,
+        [
+            "ERRORS.txt~/log.modified",
+            "d.deleted/sub.deleted/y.deleted",
+            "d.deleted/x.deleted",
+            "d.deleted~/keep.new",
+            "d.deleted~/x.deleted~.deleted",
+        ]
+    );
+
+    fs::remove_dir_all(temp).unwrap();
+}
+
+#[test]
+fn leftovers_in_the_output_do_not_shift_names() {
+    let temp = setup("naming-leftovers", &[], &[("a.txt", "fresh\n")]);
+    let out = temp.join("out");
+    fs::create_dir_all(&out).unwrap();
+    fs::write(out.join("a.txt.new"), "stale\n").unwrap();
+
+    let opts = Options {
+        on_existing: OnExisting::Overwrite,
+        ..Options::default()
+    };
+    run_bigdiff(&temp.join("base"), &temp.join("target"), &out, &opts).unwrap();
+    assert_eq!(
+        fs::read_to_string(out.join("a.txt.new")).unwrap(),
+        "fresh\n"
+    );
+    assert!(!out.join("a.txt (1).new").exists());
+
+    fs::remove_dir_all(temp).unwrap();
+}
+
+#[test]
+fn real_folders_named_like_archive_members_are_escaped() {
+    let temp = setup(
+        "naming-archive-folders",
+        &[("app.zip!/a.txt", "real one\n")],
+        &[("app.zip!/a.txt", "real two\n")],
+    );
+    for (dir, text) in [("base", "nested one\n"), ("target", "nested two\n")] {
+        let mut zip =
+            zip::ZipWriter::new(fs::File::create(temp.join(dir).join("app.zip")).unwrap());
+        zip.start_file("a.txt", zip::write::SimpleFileOptions::default())
+            .unwrap();
+        std::io::Write::write_all(&mut zip, text.as_bytes()).unwrap();
+        zip.finish().unwrap();
+    }
+
+    let opts = Options {
+        recurse_archives: true,
+        ..Options::default()
+    };
+    let mut reported = Vec::new();
+    visit_diff(&temp.join("base"), &temp.join("target"), &opts, |event| {
+        reported.push(event.rel().to_string_lossy().replace('\\', "/"));
+        Ok(())
+    })
+    .unwrap();
+    reported.sort();
+    assert_eq!(reported, ["app.zip", "app.zip!/a.txt", "app.zip!~/a.txt"]);
+
+    let out = temp.join("out");
+    run_bigdiff(&temp.join("base"), &temp.join("target"), &out, &opts).unwrap();
+    let nested = fs::read_to_string(out.join("app.zip!/a.txt.modified")).unwrap();
+    assert!(nested.contains("nested two"), "{nested}");
+    let real = fs::read_to_string(out.join("app.zip!~~/a.txt.modified")).unwrap();
+    assert!(real.contains("real two"), "{real}");
+
+    fs::remove_dir_all(temp).unwrap();
+}
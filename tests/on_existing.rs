@@ -0,0 +1,190 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use bigdiff::cli::OnExisting;
+use bigdiff::manifest::{Manifest, MANIFEST_FILE};
+use bigdiff::{run_bigdiff, Error, Options, OutputFormat, Preserve};
+
+mod common;
+use common::unique_temp_dir;
+
+// base/a.txt -> target/a.txt changes, target/new.txt is added.
+fn trees(temp: &Path) -> (PathBuf, PathBuf) {
+    let (base, target) = (temp.join("base"), temp.join("target"));
+    fs::create_dir_all(base.join("sub")).unwrap();
+    fs::create_dir_all(target.join("sub")).unwrap();
+    fs::write(base.join("sub/a.txt"), "one\n").unwrap();
+    fs::write(target.join("sub/a.txt"), "two\n").unwrap();
+    fs::write(target.join("new.txt"), "fresh\n").unwrap();
+    (base, target)
+}
+
+fn with_policy(on_existing: OnExisting) -> Options {
+    Options {
+        on_existing,
+        ..Options::default()
+    }
+}
+
+#[test]
+fn refuses_non_empty_output_by_default() {
+    let temp = unique_temp_dir("on-existing-error");
+    let (base, target) = trees(&temp);
+    let out = temp.join("out");
+    fs::create_dir_all(&out).unwrap();
+    fs::write(out.join("notes.txt"), "mine\n").unwrap();
+
+    let err = run_bigdiff(&base, &target, &out, &Options::default()).unwrap_err();
+    assert!(
+        matches!(&err, Error::OutputExists { path } if *path == out),
+        "{err}"
+    );
+    assert!(!out.join("new.txt.new").exists());
+
+    // An empty folder is fine.
+    let empty = temp.join("empty");
+    fs::create_dir_all(&empty).unwrap();
+    run_bigdiff(&base, &target, &empty, &Options::default()).unwrap();
+
+    fs::remove_dir_all(temp).unwrap();
+}
+
+#[test]
+fn clean_removes_only_listed_outputs() {
+    let temp = unique_temp_dir("on-existing-clean");
+    let (base, target) = trees(&temp);
+    let out = temp.join("out");
+    run_bigdiff(&base, &target, &out, &Options::default()).unwrap();
+
+    let manifest = Manifest::load(&out).unwrap().unwrap();
+    let listed: Vec<_> = manifest.outputs.iter().map(|e| e.path.clone()).collect();
+    assert!(listed.contains(&PathBuf::from("new.txt.new")));
+    assert!(listed.contains(&PathBuf::from("sub/a.txt.modified")));
+
+    fs::write(out.join("notes.txt"), "mine\n").unwrap();
+    fs::write(out.join("sub/keep.txt"), "mine too\n").unwrap();
+    fs::remove_file(target.join("new.txt")).unwrap();
+
+    // Without a manifest, clean does not guess.
+    let bare = temp.join("bare");
+    fs::create_dir_all(&bare).unwrap();
+    fs::write(bare.join("notes.txt"), "mine\n").unwrap();
+    let err = run_bigdiff(&base, &target, &bare, &with_policy(OnExisting::Clean)).unwrap_err();
+    assert!(matches!(err, Error::UnknownOutputs { .. }), "{err}");
+
+    run_bigdiff(&base, &target, &out, &with_policy(OnExisting::Clean)).unwrap();
+    assert!(!out.join("new.txt.new").exists());
+    assert!(out.join("sub/a.txt.modified").is_file());
+    assert!(out.join("notes.txt").is_file());
+    assert!(out.join("sub/keep.txt").is_file());
+    assert!(out.join(MANIFEST_FILE).is_file());
+
+    fs::remove_dir_all(temp).unwrap();
+}
+
+#[test]
+fn merge_keeps_existing_files_and_refuses_clashes() {
+    let temp = unique_temp_dir("on-existing-merge");
+    let (base, target) = trees(&temp);
+    let out = temp.join("out");
+    fs::create_dir_all(&out).unwrap();
+    fs::write(out.join("mine.txt"), "mine\n").unwrap();
+
+    run_bigdiff(&base, &target, &out, &with_policy(OnExisting::Merge)).unwrap();
+    assert_eq!(fs::read_to_string(out.join("mine.txt")).unwrap(), "mine\n");
+    assert_eq!(
+        fs::read_to_string(out.join("new.txt.new")).unwrap(),
+        "fresh\n"
+    );
+
+    // A second merge would replace the first run's outputs: nothing is moved.
+    fs::write(out.join("new.txt.new"), "edited\n").unwrap();
+    let err = run_bigdiff(&base, &target, &out, &with_policy(OnExisting::Merge)).unwrap_err();
+    assert!(matches!(err, Error::OutputClash { .. }), "{err}");
+    assert_eq!(
+        fs::read_to_string(out.join("new.txt.new")).unwrap(),
+        "edited\n"
+    );
+    assert!(!out.join("new.txt (1).new").exists());
+
+    // Overwrite replaces the same file instead.
+    run_bigdiff(&base, &target, &out, &with_policy(OnExisting::Overwrite)).unwrap();
+    assert_eq!(
+        fs::read_to_string(out.join("new.txt.new")).unwrap(),
+        "fresh\n"
+    );
+    assert!(out.join("mine.txt").is_file());
+
+    fs::remove_dir_all(temp).unwrap();
+}
+
+#[test]
+fn archive_outputs_only_accept_error_or_overwrite() {
+    let temp = unique_temp_dir("on-existing-archive");
+    let (base, target) = trees(&temp);
+    let zip = temp.join("diff.zip");
+    fs::write(&zip, "mine\n").unwrap();
+
+    for on_existing in [OnExisting::Clean, OnExisting::Merge] {
+        let opts = Options {
+            output_format: OutputFormat::Zip,
+            ..with_policy(on_existing)
+        };
+        let err = run_bigdiff(&base, &target, &zip, &opts).unwrap_err();
+        assert!(
+            matches!(&err, Error::UnsupportedOutputOption { .. }),
+            "{err}"
+        );
+        assert_eq!(fs::read_to_string(&zip).unwrap(), "mine\n");
+    }
+    let opts = Options {
+        output_format: OutputFormat::Zip,
+        ..with_policy(OnExisting::Merge)
+    };
+    assert_eq!(
+        run_bigdiff(&base, &target, &zip, &opts)
+            .unwrap_err()
+            .to_string(),
+        "--on-existing=merge is not supported with --output-format=zip"
+    );
+
+    let opts = Options {
+        output_format: OutputFormat::Zip,
+        ..with_policy(OnExisting::Overwrite)
+    };
+    run_bigdiff(&base, &target, &zip, &opts).unwrap();
+    assert_ne!(fs::read(&zip).unwrap(), b"mine\n");
+
+    fs::remove_dir_all(temp).unwrap();
+}
+
+#[test]
+fn archive_outputs_reject_resume_and_preserve() {
+    let temp = unique_temp_dir("on-existing-archive-options");
+    let (base, target) = trees(&temp);
+    let zip = temp.join("diff.zip");
+
+    let resume = Options {
+        output_format: OutputFormat::Zip,
+        resume: true,
+        ..Options::default()
+    };
+    let preserve = Options {
+        output_format: OutputFormat::Zip,
+        preserve: Preserve {
+            mode: true,
+            mtime: false,
+        },
+        ..Options::default()
+    };
+    for (opts, option) in [(resume, "--resume"), (preserve, "--preserve")] {
+        let err = run_bigdiff(&base, &target, &zip, &opts).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("{option} is not supported with --output-format=zip")
+        );
+    }
+    assert!(!zip.exists());
+
+    fs::remove_dir_all(temp).unwrap();
+}
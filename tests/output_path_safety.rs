@@ -1,26 +1,18 @@
 #[cfg(unix)]
 use std::fs;
-#[cfg(unix)]
-use std::path::PathBuf;
-#[cfg(unix)]
-use std::time::{SystemTime, UNIX_EPOCH};
 
 #[cfg(unix)]
-use bigdiff::{run_bigdiff, Options};
-
+use bigdiff::cli::OnExisting;
 #[cfg(unix)]
-fn unique_temp_dir() -> PathBuf {
-    let stamp = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_nanos();
-    std::env::temp_dir().join(format!("bigdiff-output-safety-{stamp}"))
-}
+use bigdiff::{run_bigdiff, run_bigdiff_with_sink, Error, FsSink, Options, OutputFormat};
+
+mod common;
+use common::unique_temp_dir;
 
 #[cfg(unix)]
 #[test]
 fn rejects_symlinked_output_subdirectories() {
-    let temp = unique_temp_dir();
+    let temp = unique_temp_dir("output-safety");
     let base = temp.join("base");
     let target = temp.join("target");
     let out = temp.join("out");
@@ -39,23 +31,54 @@ fn rejects_symlinked_output_subdirectories() {
     let opts = Options {
         normalize_eol: false,
         max_text_size: 1_000_000,
-        ignore_patterns: vec![],
-        dry_run: false,
+        // The planted symlink makes `out` non-empty.
+        on_existing: OnExisting::Overwrite,
+        ..Options::default()
     };
 
-    let err = run_bigdiff(&base, &target, &out, &opts)
-        .unwrap_err()
-        .to_string();
-    assert!(err.contains("symlinked output path component"));
+    let err = run_bigdiff(&base, &target, &out, &opts).unwrap_err();
+    assert!(
+        matches!(&err, Error::SymlinkedOutputComponent { path } if *path == out.join("nested")),
+        "{err}"
+    );
     assert!(!escape.join("demo.txt.modified").exists());
 
     fs::remove_dir_all(temp).unwrap();
 }
 
+#[cfg(unix)]
+#[test]
+fn keep_going_still_aborts_on_symlinked_outputs() {
+    let temp = unique_temp_dir("output-safety");
+    let (base, target, out) = (temp.join("base"), temp.join("target"), temp.join("out"));
+    let escape = temp.join("escape");
+    for root in [&base, &target] {
+        fs::create_dir_all(root.join("nested")).unwrap();
+        fs::write(root.join("nested/demo.txt"), format!("{root:?}\n")).unwrap();
+    }
+    fs::create_dir_all(&out).unwrap();
+    fs::create_dir_all(&escape).unwrap();
+    std::os::unix::fs::symlink(&escape, out.join("nested")).unwrap();
+
+    // Writing straight into the folder, so the writer itself meets the symlink.
+    let opts = Options {
+        keep_going: true,
+        ..Options::default()
+    };
+    let err = run_bigdiff_with_sink(&base, &target, &mut FsSink::new(&out), &opts).unwrap_err();
+    assert!(
+        matches!(&err, Error::SymlinkedOutputComponent { path } if *path == out.join("nested")),
+        "{err}"
+    );
+    assert!(fs::read_dir(&escape).unwrap().next().is_none());
+
+    fs::remove_dir_all(temp).unwrap();
+}
+
 #[cfg(unix)]
 #[test]
 fn rejects_symlinked_output_ancestors() {
-    let temp = unique_temp_dir();
+    let temp = unique_temp_dir("output-safety");
     let base = temp.join("base");
     let target = temp.join("target");
     let real_parent = temp.join("real-parent");
@@ -74,15 +97,47 @@ fn rejects_symlinked_output_ancestors() {
     let opts = Options {
         normalize_eol: false,
         max_text_size: 1_000_000,
-        ignore_patterns: vec![],
-        dry_run: false,
+        ..Options::default()
     };
 
-    let err = run_bigdiff(&base, &target, &out, &opts)
-        .unwrap_err()
-        .to_string();
-    assert!(err.contains("symlinked output path component"));
+    let err = run_bigdiff(&base, &target, &out, &opts).unwrap_err();
+    assert!(
+        matches!(&err, Error::SymlinkedOutputComponent { path } if *path == link_parent),
+        "{err}"
+    );
     assert!(!real_parent.join("out/nested/demo.txt.modified").exists());
 
     fs::remove_dir_all(temp).unwrap();
 }
+
+#[cfg(unix)]
+#[test]
+fn rejects_symlinked_archive_output_paths() {
+    let temp = unique_temp_dir("output-safety");
+    let base = temp.join("base");
+    let target = temp.join("target");
+    let real_parent = temp.join("real-parent");
+    let link_parent = temp.join("link-parent");
+
+    fs::create_dir_all(&base).unwrap();
+    fs::create_dir_all(&target).unwrap();
+    fs::create_dir_all(&real_parent).unwrap();
+    fs::write(base.join("demo.txt"), "old\n").unwrap();
+    fs::write(target.join("demo.txt"), "new\n").unwrap();
+
+    std::os::unix::fs::symlink(&real_parent, &link_parent).unwrap();
+
+    let opts = Options {
+        output_format: OutputFormat::Zip,
+        ..Options::default()
+    };
+
+    let err = run_bigdiff(&base, &target, &link_parent.join("diff.zip"), &opts).unwrap_err();
+    assert!(
+        matches!(&err, Error::SymlinkedOutputComponent { path } if *path == link_parent),
+        "{err}"
+    );
+    assert!(!real_parent.join("diff.zip").exists());
+
+    fs::remove_dir_all(temp).unwrap();
+}
@@ -0,0 +1,135 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use bigdiff::{run_bigdiff, ArchiveLimits, InputTree, Options, OutputFormat};
+use walkdir::WalkDir;
+
+mod common;
+use common::unique_temp_dir;
+
+// Enough entries of every kind that a hash-ordered walk would visit them differently.
+fn fill(temp: &Path) -> (PathBuf, PathBuf) {
+    let (base, target) = (temp.join("base"), temp.join("target"));
+    for i in 0..20 {
+        let (a, b) = (base.join(format!("dir{i}")), target.join(format!("dir{i}")));
+        fs::create_dir_all(&a).unwrap();
+        fs::create_dir_all(&b).unwrap();
+        fs::write(a.join("same.txt"), "same\n").unwrap();
+        fs::write(b.join("same.txt"), "same\n").unwrap();
+        fs::write(a.join("text.txt"), format!("line {i}\n")).unwrap();
+        fs::write(b.join("text.txt"), format!("line {}\n", i + 1)).unwrap();
+        fs::write(a.join("blob.bin"), [0u8, 1, i]).unwrap();
+        fs::write(b.join("blob.bin"), [0u8, 2, i]).unwrap();
+        fs::write(a.join(format!("gone{i}.txt")), "old\n").unwrap();
+        fs::write(b.join(format!("new{i}.txt")), "new\n").unwrap();
+        fs::create_dir_all(base.join(format!("removed{i}/inner"))).unwrap();
+        fs::write(base.join(format!("removed{i}/inner/f.txt")), "x\n").unwrap();
+    }
+    (base, target)
+}
+
+fn write_zip(path: &Path, files: &[(&str, &[u8])]) {
+    let mut zip = zip::ZipWriter::new(File::create(path).unwrap());
+    for (name, content) in files {
+        zip.start_file(*name, zip::write::SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(content).unwrap();
+    }
+    zip.finish().unwrap();
+}
+
+fn snapshot(root: &Path) -> BTreeMap<PathBuf, Option<Vec<u8>>> {
+    WalkDir::new(root)
+        .into_iter()
+        .map(|entry| entry.unwrap())
+        .map(|entry| {
+            let rel = entry.path().strip_prefix(root).unwrap().to_path_buf();
+            let contents = entry
+                .file_type()
+                .is_file()
+                .then(|| fs::read(entry.path()).unwrap());
+            (rel, contents)
+        })
+        .collect()
+}
+
+#[test]
+fn identical_inputs_give_byte_identical_outputs() {
+    let temp = unique_temp_dir("reproducible");
+    let (base, target) = fill(&temp);
+
+    let (first, second) = (temp.join("first"), temp.join("second"));
+    run_bigdiff(&base, &target, &first, &Options::default()).unwrap();
+    run_bigdiff(&base, &target, &second, &Options::default()).unwrap();
+    let (first, second) = (snapshot(&first), snapshot(&second));
+    assert!(first.contains_key(Path::new("bigdiff-manifest.json")));
+    assert_eq!(first, second);
+
+    for (format, name) in [(OutputFormat::Zip, "zip"), (OutputFormat::TarGz, "tar.gz")] {
+        let opts = Options {
+            output_format: format,
+            ..Options::default()
+        };
+        let (first, second) = (
+            temp.join(format!("first.{name}")),
+            temp.join(format!("second.{name}")),
+        );
+        run_bigdiff(&base, &target, &first, &opts).unwrap();
+        run_bigdiff(&base, &target, &second, &opts).unwrap();
+        assert_eq!(
+            fs::read(first).unwrap(),
+            fs::read(second).unwrap(),
+            "{name}"
+        );
+    }
+
+    fs::remove_dir_all(temp).unwrap();
+}
+
+#[test]
+fn archive_inputs_and_notes_are_reproducible() {
+    let temp = unique_temp_dir("reproducible-archives");
+    fs::create_dir_all(&temp).unwrap();
+    let (base, target) = (temp.join("base.zip"), temp.join("target.zip"));
+    // A binary change and a CSS change whose inline markers would close a new comment early both
+    // leave a NOTE behind.
+    write_zip(
+        &base,
+        &[("blob.bin", b"\x00\x01a"), ("style.css", b"b { }\n")],
+    );
+    write_zip(
+        &target,
+        &[
+            ("blob.bin", b"\x00\x02b"),
+            ("style.css", b"/* start\nb { }\n*/\n"),
+        ],
+    );
+
+    let opts = Options::default();
+    // Each run extracts the archives into fresh temporary folders.
+    let run = |out: &Path| {
+        let (a, b) = (
+            InputTree::open(&base, &ArchiveLimits::default()).unwrap(),
+            InputTree::open(&target, &ArchiveLimits::default()).unwrap(),
+        );
+        run_bigdiff(&a.root, &b.root, out, &opts).unwrap();
+    };
+    let (first, second) = (temp.join("first"), temp.join("second"));
+    run(&first);
+    run(&second);
+
+    for name in ["blob.bin.modified.NOTE.txt", "style.css.modified.NOTE.txt"] {
+        let note = fs::read_to_string(first.join(name)).unwrap();
+        let member = name.split(".modified").next().unwrap();
+        assert!(note.contains(&format!("File: {member:?}")), "{note}");
+        assert!(
+            !note.contains(&*std::env::temp_dir().to_string_lossy()),
+            "{note}"
+        );
+    }
+    assert_eq!(snapshot(&first), snapshot(&second));
+
+    fs::remove_dir_all(temp).unwrap();
+}
@@ -0,0 +1,147 @@
+use std::fs;
+
+use bigdiff::cli::OnExisting;
+use bigdiff::staging::{COMPLETE_MARKER, JOURNAL_FILE, STAGING_DIR};
+use bigdiff::{run_bigdiff, Error, Options};
+
+mod common;
+use common::unique_temp_dir;
+
+#[test]
+fn completed_runs_leave_a_marker_and_no_staging() {
+    let temp = unique_temp_dir("staging-done");
+    fs::create_dir_all(temp.join("base/sub")).unwrap();
+    fs::create_dir_all(temp.join("target/sub")).unwrap();
+    fs::write(temp.join("base/sub/a.txt"), "one\n").unwrap();
+    fs::write(temp.join("target/sub/a.txt"), "two\n").unwrap();
+
+    let out = temp.join("out");
+    run_bigdiff(
+        &temp.join("base"),
+        &temp.join("target"),
+        &out,
+        &Options::default(),
+    )
+    .unwrap();
+
+    assert!(out.join(COMPLETE_MARKER).is_file());
+    assert!(!out.join(STAGING_DIR).exists());
+    assert!(out.join("sub/a.txt.modified").is_file());
+}
+
+// An unreadable common file (`/proc/self/mem` cannot be read at offset 0) aborts the first run
+// after the new and deleted files were written; the resumed run keeps those outputs.
+#[cfg(target_os = "linux")]
+#[test]
+fn interrupted_runs_stay_staged_and_resume_keeps_finished_outputs() {
+    let temp = unique_temp_dir("staging-resume");
+    let (base, target, out) = (temp.join("base"), temp.join("target"), temp.join("out"));
+    fs::create_dir_all(&base).unwrap();
+    fs::create_dir_all(&target).unwrap();
+    fs::write(base.join("gone.txt"), "bye\n").unwrap();
+    fs::write(target.join("fresh.txt"), "first\n").unwrap();
+    fs::write(base.join("broken.txt"), "old\n").unwrap();
+    std::os::unix::fs::symlink("/proc/self/mem", target.join("broken.txt")).unwrap();
+
+    let opts = Options::default();
+    assert!(run_bigdiff(&base, &target, &out, &opts).is_err());
+    assert!(!out.join(COMPLETE_MARKER).exists());
+    assert!(!out.join("fresh.txt.new").exists());
+    assert!(out.join(STAGING_DIR).join("fresh.txt.new").is_file());
+
+    // A half-written output from the interrupted entry must not survive the resume.
+    fs::write(out.join(STAGING_DIR).join("broken.txt.modified"), "partial").unwrap();
+
+    fs::remove_file(target.join("broken.txt")).unwrap();
+    fs::write(target.join("broken.txt"), "new\n").unwrap();
+    fs::write(target.join("fresh.txt"), "second\n").unwrap();
+
+    let resumed = Options {
+        resume: true,
+        ..Options::default()
+    };
+    let counters = run_bigdiff(&base, &target, &out, &resumed).unwrap();
+    assert_eq!(counters.new_files, 1);
+    assert_eq!(counters.del_files, 1);
+    assert_eq!(counters.mod_text, 1);
+
+    assert!(out.join(COMPLETE_MARKER).is_file());
+    assert!(!out.join(STAGING_DIR).exists());
+    // Finished before the interruption, so it was not rewritten.
+    assert_eq!(
+        fs::read_to_string(out.join("fresh.txt.new")).unwrap(),
+        "first\n"
+    );
+    assert!(out.join("gone.txt.deleted").is_file());
+    assert!(fs::read_to_string(out.join("broken.txt.modified"))
+        .unwrap()
+        .contains("new"));
+
+    fs::remove_dir_all(temp).unwrap();
+}
+
+#[test]
+fn resume_without_a_journal_applies_the_on_existing_policy() {
+    let temp = unique_temp_dir("staging-resume-foreign");
+    fs::create_dir_all(temp.join("base")).unwrap();
+    fs::create_dir_all(temp.join("target")).unwrap();
+    fs::write(temp.join("target/a.txt"), "new\n").unwrap();
+    let out = temp.join("out");
+    fs::create_dir_all(&out).unwrap();
+    fs::write(out.join("unrelated.txt"), "not ours\n").unwrap();
+
+    let resumed = Options {
+        resume: true,
+        ..Options::default()
+    };
+    let err = run_bigdiff(&temp.join("base"), &temp.join("target"), &out, &resumed).unwrap_err();
+    assert!(matches!(err, Error::OutputExists { .. }), "{err}");
+    assert!(!out.join("a.txt.new").exists());
+    assert!(!out.join(STAGING_DIR).exists());
+
+    // An empty folder has nothing to protect, so resuming there is just a fresh run.
+    let fresh = temp.join("fresh");
+    run_bigdiff(&temp.join("base"), &temp.join("target"), &fresh, &resumed).unwrap();
+    assert!(fresh.join("a.txt.new").is_file());
+
+    fs::remove_dir_all(temp).unwrap();
+}
+
+// A folder in the way of `z.txt.new` makes the move fail after `a.txt.modified` was moved; the
+// journal survives, and the resumed run lists both outputs.
+#[test]
+fn a_failed_finish_keeps_the_journal_for_resume() {
+    let temp = unique_temp_dir("staging-finish");
+    let (base, target, out) = (temp.join("base"), temp.join("target"), temp.join("out"));
+    fs::create_dir_all(&base).unwrap();
+    fs::create_dir_all(&target).unwrap();
+    fs::write(base.join("a.txt"), "one\n").unwrap();
+    fs::write(target.join("a.txt"), "two\n").unwrap();
+    fs::write(target.join("z.txt"), "new\n").unwrap();
+    fs::create_dir_all(out.join("z.txt.new/blocker")).unwrap();
+
+    let opts = Options {
+        on_existing: OnExisting::Overwrite,
+        ..Options::default()
+    };
+    assert!(run_bigdiff(&base, &target, &out, &opts).is_err());
+    assert!(out.join(STAGING_DIR).join(JOURNAL_FILE).is_file());
+    assert!(out.join("a.txt.modified").is_file());
+    assert!(!out.join(COMPLETE_MARKER).exists());
+
+    fs::remove_dir_all(out.join("z.txt.new")).unwrap();
+    let opts = Options {
+        resume: true,
+        ..Options::default()
+    };
+    run_bigdiff(&base, &target, &out, &opts).unwrap();
+    assert!(out.join(COMPLETE_MARKER).is_file());
+    assert!(!out.join(STAGING_DIR).exists());
+    assert_eq!(fs::read_to_string(out.join("z.txt.new")).unwrap(), "new\n");
+
+    let manifest = fs::read_to_string(out.join("bigdiff-manifest.json")).unwrap();
+    assert!(manifest.contains("\"a.txt.modified\""), "{manifest}");
+    assert!(manifest.contains("\"z.txt.new\""), "{manifest}");
+
+    fs::remove_dir_all(temp).unwrap();
+}